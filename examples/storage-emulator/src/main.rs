@@ -0,0 +1,26 @@
+use openapi_rs::emulator::StorageEmulator;
+use std::env;
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// Usage: `storage-emulator [root] [addr]`, then point `OpenApiCloudEndpoint`
+/// at `http://<addr>`. Requests are signed with `OpenApiAppKey` and
+/// `OpenApiAppSecret` as read from the environment or `.env`.
+#[tokio::main]
+pub async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    dotenvy::dotenv().ok();
+    let mut args = env::args().skip(1);
+    let root = args.next().unwrap_or_else(|| "storage".to_string());
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let app_key = env::var("OpenApiAppKey")?;
+    let app_secret = env::var("OpenApiAppSecret")?;
+
+    tokio::fs::create_dir_all(&root).await?;
+    let listener = TcpListener::bind(&addr).await?;
+    info!("serving {} on http://{}", root, listener.local_addr()?);
+    StorageEmulator::new(root)
+        .with_credential(&app_key, &app_secret)
+        .serve(listener)
+        .await
+}
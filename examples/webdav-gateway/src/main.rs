@@ -0,0 +1,27 @@
+use openapi_rs::common::client::OpenApiClient;
+use openapi_rs::common::config::{EndpointType, OpenApiConfig};
+use openapi_rs::common::remote_path::RemotePath;
+use openapi_rs::webdav::WebDavGateway;
+use std::env;
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// Usage: `webdav-gateway [remote dir] [addr]`, then connect a WebDAV client
+/// to `http://<addr>/`. The remote directory defaults to the user root.
+#[tokio::main]
+pub async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    dotenvy::dotenv()?;
+    let config = OpenApiConfig::new().load_from_env()?;
+    let mut args = env::args().skip(1);
+    let root = match args.next() {
+        Some(root) => RemotePath::resolve(root, &config)?,
+        None => RemotePath::home(&config)?,
+    };
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8081".to_string());
+    let client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
+
+    let listener = TcpListener::bind(&addr).await?;
+    info!("serving {} on http://{}", root, listener.local_addr()?);
+    WebDavGateway::new(&client, root).serve(listener).await
+}
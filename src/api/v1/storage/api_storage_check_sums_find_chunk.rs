@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use crate::model::file::{Chunk, ChunkChecksum};
 use bytes::Bytes;
 use reqwest::{Method, Response};
@@ -10,7 +11,7 @@ use serde::{Deserialize, Serialize};
 #[serde(default)]
 pub struct ApiStorageCheckSumsFindChunkRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "BlockSize")]
     pub block_size: Option<isize>,
     #[serde(rename = "BeginChunkOffset")]
@@ -27,8 +28,8 @@ impl ApiStorageCheckSumsFindChunkRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_block_size(mut self, block_size: isize) -> Self {
@@ -89,11 +90,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageCheckSumsFindChunkRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use crate::model::file::ChunkChecksum;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
@@ -10,7 +11,7 @@ use std::collections::HashMap;
 #[serde(default)]
 pub struct ApiStorageChunkCheckSumsRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "BlockSize")]
     pub block_size: Option<isize>,
     #[serde(rename = "BeginChunkOffset")]
@@ -25,8 +26,8 @@ impl ApiStorageChunkCheckSumsRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_block_size(mut self, block_size: isize) -> Self {
@@ -61,7 +62,7 @@ impl HttpBuilder for ApiStorageChunkCheckSumsRequest {
             let request_fn: RequestFn = Box::new(move || {
                 let mut queries = HashMap::new();
                 if let Some(path) = self.path {
-                    queries.insert("Path".to_string(), path);
+                    queries.insert("Path".to_string(), path.to_string());
                 }
                 if let Some(block_size) = self.block_size {
                     queries.insert("BlockSize".to_string(), block_size.to_string());
@@ -104,11 +105,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageChunkCheckSumsRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
@@ -1,17 +1,22 @@
+use crate::common::content_disposition::ContentDisposition;
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BytesStream, HttpBuilder, HttpFn, HttpStreamBuilder, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
+use anyhow::anyhow;
 use bytes::Bytes;
-use regex::Regex;
+use futures::TryStreamExt;
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, HeaderMap};
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use tracing::warn;
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ApiStorageDownloadRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "RangeStart")]
     pub range_start: Option<isize>,
     #[serde(rename = "RangeEnd")]
@@ -22,8 +27,8 @@ impl ApiStorageDownloadRequest {
     pub fn new() -> Self {
         Default::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_range_start(mut self, range_start: isize) -> Self {
@@ -35,11 +40,12 @@ impl ApiStorageDownloadRequest {
         self
     }
 
+    #[allow(clippy::collapsible_if)]
     fn request_fn(self) -> RequestFn {
         let request_fn: RequestFn = Box::new(move || {
             let mut queries = HashMap::new();
             if let Some(path) = &self.path {
-                queries.insert("Path".to_string(), path.clone());
+                queries.insert("Path".to_string(), path.to_string());
             }
             if let Some(range_start) = self.range_start {
                 if let Some(range_end) = self.range_end {
@@ -64,9 +70,10 @@ impl ApiStorageDownloadRequest {
 #[serde(default)]
 pub struct ApiStorageDownloadResponse {
     #[serde(rename = "FileName")]
-    pub file_name: String,
+    pub file_name: Option<String>,
     #[serde(rename = "FileType")]
-    pub file_type: String,
+    pub file_type: Option<String>,
+    /// `Content-Length` when sent, otherwise the length of the received body.
     #[serde(rename = "FileSize")]
     pub file_size: isize,
     #[serde(rename = "Data", skip)]
@@ -80,34 +87,18 @@ impl HttpBuilder for ApiStorageDownloadRequest {
         Box::new(move || {
             let response_fn: AsyncResponseFn<Self::Response> = Box::new(|response: Response| {
                 Box::pin(async move {
-                    let mut download_response = ApiStorageDownloadResponse::default();
-                    let file_name_regex = Regex::new(r#"attachment; filename="(.*?)""#)?;
-                    download_response.file_name = response
-                        .headers()
-                        .get("Content-Disposition")
-                        .and_then(|v| v.to_str().ok())
-                        .and_then(|s| {
-                            file_name_regex
-                                .captures(s)
-                                .and_then(|caps| caps.get(1))
-                                .map(|m| m.as_str().to_owned())
-                        })
-                        .unwrap();
-                    download_response.file_type = response
-                        .headers()
-                        .get("Content-Type")
-                        .unwrap()
-                        .to_str()?
-                        .to_owned();
-                    download_response.file_size = response
-                        .headers()
-                        .get("Content-Length")
-                        .unwrap()
-                        .to_str()?
-                        .parse::<isize>()?;
-                    download_response.data = Some(response.bytes().await?);
-
-                    Ok(download_response)
+                    let headers = response.headers();
+                    let file_name = file_name(headers);
+                    let file_type = header_value(headers, CONTENT_TYPE.as_str());
+                    let content_length = content_length(headers)?;
+                    let data = response.bytes().await?;
+
+                    Ok(ApiStorageDownloadResponse {
+                        file_name,
+                        file_type,
+                        file_size: content_length.unwrap_or(data.len() as isize),
+                        data: Some(data),
+                    })
                 })
             });
             (self.request_fn(), response_fn)
@@ -117,6 +108,10 @@ impl HttpBuilder for ApiStorageDownloadRequest {
 
 #[derive(derive_more::Debug, Default)]
 pub struct DownloadStreamResponse {
+    pub file_name: Option<String>,
+    pub file_type: Option<String>,
+    /// `Content-Length`, absent for chunked responses.
+    pub file_size: Option<isize>,
     #[debug(skip)]
     pub stream: Option<BytesStream>,
 }
@@ -128,8 +123,14 @@ impl HttpStreamBuilder for ApiStorageDownloadRequest {
         Box::new(move || {
             let response_fn: AsyncResponseFn<Self::Response> = Box::new(|response: Response| {
                 Box::pin(async move {
+                    let headers = response.headers();
                     Ok(DownloadStreamResponse {
-                        stream: Some(Box::pin(response.bytes_stream())),
+                        file_name: file_name(headers),
+                        file_type: header_value(headers, CONTENT_TYPE.as_str()),
+                        file_size: content_length(headers)?,
+                        stream: Some(Box::pin(
+                            response.bytes_stream().map_err(std::io::Error::other),
+                        )),
                     })
                 })
             });
@@ -138,24 +139,96 @@ impl HttpStreamBuilder for ApiStorageDownloadRequest {
     }
 }
 
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+/// The file name from `Content-Disposition`. A header that does not parse is
+/// retried without its extended `name*` parameters, so a bad `filename*`
+/// falls back to `filename`; otherwise the name is dropped with a warning.
+fn file_name(headers: &HeaderMap) -> Option<String> {
+    let value = header_value(headers, CONTENT_DISPOSITION.as_str())?;
+    let content_disposition = ContentDisposition::parse(&value).or_else(|e| {
+        warn!("ignoring invalid Content-Disposition {:?}: {:#}", value, e);
+        let plain: Vec<&str> = value
+            .split(';')
+            .filter(|param| {
+                !param
+                    .split('=')
+                    .next()
+                    .is_some_and(|name| name.trim().ends_with('*'))
+            })
+            .collect();
+        ContentDisposition::parse(&plain.join(";"))
+    });
+    content_disposition.ok()?.filename().map(str::to_string)
+}
+
+fn content_length(headers: &HeaderMap) -> anyhow::Result<Option<isize>> {
+    let Some(value) = header_value(headers, CONTENT_LENGTH.as_str()) else {
+        return Ok(None);
+    };
+    let content_length = value
+        .trim()
+        .parse::<isize>()
+        .map_err(|e| anyhow!("invalid Content-Length {:?}: {}", value, e))?;
+    Ok(Some(content_length))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::common::client::OpenApiClient;
     use crate::common::config::{EndpointType, OpenApiConfig};
     use futures_util::stream::StreamExt;
+    use reqwest::header::HeaderValue;
     use tracing::info;
 
+    #[test]
+    fn test_download_metadata() -> anyhow::Result<()> {
+        let mut headers = HeaderMap::new();
+        assert_eq!(file_name(&headers), None);
+        assert_eq!(content_length(&headers)?, None);
+
+        headers.insert(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_static("attachment; filename*=UTF-8''r%C3%BCnner.py"),
+        );
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("42"));
+        assert_eq!(file_name(&headers).as_deref(), Some("rünner.py"));
+        assert_eq!(content_length(&headers)?, Some(42));
+
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("lots"));
+        assert!(content_length(&headers).is_err());
+
+        headers.insert(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_static("attachment; filename=\"runner.py\"; filename*=UTF-16''%FF"),
+        );
+        assert_eq!(file_name(&headers).as_deref(), Some("runner.py"));
+        headers.insert(
+            CONTENT_DISPOSITION,
+            HeaderValue::from_static("attachment; filename*=UTF-8''%FF"),
+        );
+        assert_eq!(file_name(&headers), None);
+        headers.insert(CONTENT_DISPOSITION, HeaderValue::from_static("; ="));
+        assert_eq!(file_name(&headers), None);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_api_storage_download() -> anyhow::Result<()> {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageDownloadRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
@@ -168,11 +241,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageDownloadRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .stream_builder();
         let mut response = client.send(http_fn).await?;
         while let Some(data) = response
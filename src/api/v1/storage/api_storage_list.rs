@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use crate::model::file::FileInfo;
 use bytes::Bytes;
 use reqwest::{Method, Response};
@@ -11,7 +12,7 @@ use std::collections::HashMap;
 #[serde(default)]
 pub struct ApiStorageListRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "FilterRegexp")]
     pub filter_regexp: Option<String>,
     #[serde(rename = "FilterRegexpList")]
@@ -26,8 +27,8 @@ impl ApiStorageListRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_filter_regexp(mut self, filter_regexp: String) -> Self {
@@ -66,7 +67,7 @@ impl HttpBuilder for ApiStorageListRequest {
             let request_fn: RequestFn = Box::new(move || {
                 let mut query_params = HashMap::new();
                 if let Some(ref path) = self.path {
-                    query_params.insert("Path".to_string(), path.clone());
+                    query_params.insert("Path".to_string(), path.to_string());
                 }
                 if let Some(ref filter_regexp) = self.filter_regexp {
                     query_params.insert("FilterRegexp".to_string(), filter_regexp.clone());
@@ -104,11 +105,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageListRequest::new()
-            .with_path(format!("/{}", user_id))
+            .with_path(home)
             .with_page_offset(0)
             .with_page_size(10)
             .builder();
@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use bytes::Bytes;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
@@ -9,7 +10,7 @@ use serde::{Deserialize, Serialize};
 #[serde(default)]
 pub struct ApiStorageMkDirRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "IgnoreExist")]
     pub ignore_exist: Option<bool>,
 }
@@ -18,8 +19,8 @@ impl ApiStorageMkDirRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_ignore_exist(mut self, ignore_exist: bool) -> Self {
@@ -61,11 +62,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageMkDirRequest::new()
-            .with_path(format!("/{}/abcd", user_id))
+            .with_path(home.join("abcd")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use bytes::Bytes;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
@@ -9,21 +10,21 @@ use serde::{Deserialize, Serialize};
 #[serde(default)]
 pub struct ApiStorageMoveRequest {
     #[serde(rename = "Src")]
-    pub src_path: Option<String>,
+    pub src_path: Option<RemotePath>,
     #[serde(rename = "Dest")]
-    pub dest_path: Option<String>,
+    pub dest_path: Option<RemotePath>,
 }
 
 impl ApiStorageMoveRequest {
     pub fn new() -> Self {
         Default::default()
     }
-    pub fn with_src_path(mut self, src_path: String) -> Self {
-        self.src_path = Some(src_path);
+    pub fn with_src_path(mut self, src_path: impl Into<RemotePath>) -> Self {
+        self.src_path = Some(src_path.into());
         self
     }
-    pub fn with_dest_path(mut self, dest_path: String) -> Self {
-        self.dest_path = Some(dest_path);
+    pub fn with_dest_path(mut self, dest_path: impl Into<RemotePath>) -> Self {
+        self.dest_path = Some(dest_path.into());
         self
     }
 }
@@ -66,12 +67,12 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageMoveRequest::new()
-            .with_src_path(format!("/{}/runner.py", user_id))
-            .with_dest_path(format!("/{}/tmp/runner.py", user_id))
+            .with_src_path(home.join("runner.py")?)
+            .with_dest_path(home.join("tmp/runner.py")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
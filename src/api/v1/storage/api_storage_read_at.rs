@@ -1,7 +1,10 @@
+use crate::common::compressor::Compressor;
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BytesStream, HttpBuilder, HttpFn, HttpStreamBuilder, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use bytes::Bytes;
+use futures::TryStreamExt;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -10,9 +13,9 @@ use std::collections::HashMap;
 #[serde(default)]
 pub struct ApiStorageReadAtRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "Compressor")]
-    pub compressor: Option<String>,
+    pub compressor: Option<Compressor>,
     #[serde(rename = "Offset")]
     pub offset: Option<isize>,
     #[serde(rename = "Length")]
@@ -23,11 +26,11 @@ impl ApiStorageReadAtRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
-    pub fn with_compressor(mut self, compressor: String) -> Self {
+    pub fn with_compressor(mut self, compressor: Compressor) -> Self {
         self.compressor = Some(compressor);
         self
     }
@@ -44,10 +47,10 @@ impl ApiStorageReadAtRequest {
         let request_fn: RequestFn = Box::new(move || {
             let mut queries = HashMap::new();
             if let Some(path) = self.path {
-                queries.insert("Path".to_string(), path);
+                queries.insert("Path".to_string(), path.to_string());
             }
             if let Some(compressor) = self.compressor {
-                queries.insert("Compressor".to_string(), compressor);
+                queries.insert("Compressor".to_string(), compressor.to_string());
             }
             if let Some(offset) = self.offset {
                 queries.insert("Offset".to_string(), offset.to_string());
@@ -77,13 +80,18 @@ impl HttpBuilder for ApiStorageReadAtRequest {
     type Response = ApiStorageReadAtResponse;
     fn builder(self) -> HttpFn<Self::Response> {
         Box::new(move || {
-            let response_fn: AsyncResponseFn<Self::Response> = Box::new(|response: Response| {
-                Box::pin(async move {
-                    Ok(ApiStorageReadAtResponse {
-                        data: Some(response.bytes().await?),
+            let compressor = self.compressor.unwrap_or_default();
+            let response_fn: AsyncResponseFn<Self::Response> =
+                Box::new(move |response: Response| {
+                    Box::pin(async move {
+                        let data = response.bytes().await?;
+                        let data = match compressor {
+                            Compressor::None => data,
+                            _ => Bytes::from(compressor.decompress(&data)?),
+                        };
+                        Ok(ApiStorageReadAtResponse { data: Some(data) })
                     })
-                })
-            });
+                });
             (self.request_fn(), response_fn)
         })
     }
@@ -100,13 +108,21 @@ impl HttpStreamBuilder for ApiStorageReadAtRequest {
 
     fn stream_builder(self) -> HttpFn<Self::Response> {
         Box::new(move || {
-            let response_fn: AsyncResponseFn<Self::Response> = Box::new(|response: Response| {
-                Box::pin(async move {
-                    Ok(ApiStorageReadAtStreamResponse {
-                        stream: Some(Box::pin(response.bytes_stream())),
+            let compressor = self.compressor.unwrap_or_default();
+            let response_fn: AsyncResponseFn<Self::Response> =
+                Box::new(move |response: Response| {
+                    Box::pin(async move {
+                        let stream: BytesStream =
+                            Box::pin(response.bytes_stream().map_err(std::io::Error::other));
+                        let stream = match compressor {
+                            Compressor::None => stream,
+                            _ => compressor.decompress_stream(stream),
+                        };
+                        Ok(ApiStorageReadAtStreamResponse {
+                            stream: Some(stream),
+                        })
                     })
-                })
-            });
+                });
             (self.request_fn(), response_fn)
         })
     }
@@ -125,11 +141,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageReadAtRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .with_offset(0)
             .with_length(1024)
             .builder();
@@ -144,11 +160,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageReadAtRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .with_offset(0)
             .with_length(1024)
             .stream_builder();
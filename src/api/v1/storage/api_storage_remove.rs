@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use bytes::Bytes;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
@@ -9,7 +10,7 @@ use serde::{Deserialize, Serialize};
 #[serde(default)]
 pub struct ApiStorageRemoveRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "IgnoreNotExist")]
     pub ignore_not_exist: Option<bool>,
 }
@@ -18,8 +19,8 @@ impl ApiStorageRemoveRequest {
     pub fn new() -> Self {
         Default::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_ignore_not_exist(mut self, ignore_not_exist: bool) -> Self {
@@ -66,11 +67,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageRemoveRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .with_ignore_not_exist(true)
             .builder();
         let response = client.send(http_fn).await?;
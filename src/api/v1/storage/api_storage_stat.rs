@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use crate::model::file::FileInfo;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
@@ -10,15 +11,15 @@ use std::collections::HashMap;
 #[serde(default)]
 pub struct ApiStorageStatRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
 }
 
 impl ApiStorageStatRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
 }
@@ -37,7 +38,7 @@ impl HttpBuilder for ApiStorageStatRequest {
             let request_fn: RequestFn = Box::new(move || {
                 let mut queries = HashMap::new();
                 if let Some(path) = self.path {
-                    queries.insert("Path".to_string(), path);
+                    queries.insert("Path".to_string(), path.to_string());
                 }
                 BaseRequest {
                     method: Method::GET,
@@ -65,11 +66,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageStatRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
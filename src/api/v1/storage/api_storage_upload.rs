@@ -1,6 +1,7 @@
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use bytes::Bytes;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
@@ -10,7 +11,7 @@ use std::collections::HashMap;
 #[serde(default)]
 pub struct ApiStorageUploadRequest {
     #[serde(rename = "Path")]
-    path: Option<String>,
+    path: Option<RemotePath>,
     #[serde(rename = "Content")]
     content: Option<Vec<u8>>,
     #[serde(rename = "Overwrite")]
@@ -21,8 +22,8 @@ impl ApiStorageUploadRequest {
     pub fn new() -> Self {
         Default::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
     pub fn with_content(mut self, content: Vec<u8>) -> Self {
@@ -47,7 +48,7 @@ impl HttpBuilder for ApiStorageUploadRequest {
             let request_fn: RequestFn = Box::new(move || {
                 let mut queries = HashMap::new();
                 if let Some(path) = &self.path {
-                    queries.insert("Path".to_string(), path.clone());
+                    queries.insert("Path".to_string(), path.to_string());
                 }
                 if let Some(overwrite) = self.overwrite {
                     queries.insert("Overwrite".to_string(), overwrite.to_string());
@@ -83,11 +84,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageUploadRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .with_content("print('hello world!')".as_bytes().to_vec())
             .with_overwrite(true)
             .builder();
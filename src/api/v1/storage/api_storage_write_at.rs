@@ -1,34 +1,39 @@
+use crate::common::compressor::Compressor;
 use crate::common::define::{
     AsyncResponseFn, BaseRequest, BaseResponse, HttpBuilder, HttpFn, RequestFn,
 };
+use crate::common::remote_path::RemotePath;
 use crate::model::file::FileInfo;
 use bytes::Bytes;
 use reqwest::{Method, Response};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ApiStorageWriteAtRequest {
     #[serde(rename = "Path")]
-    pub path: Option<String>,
+    pub path: Option<RemotePath>,
     #[serde(rename = "Compressor")]
-    pub compressor: Option<String>,
+    pub compressor: Option<Compressor>,
     #[serde(rename = "Offset")]
     pub offset: Option<isize>,
     #[serde(rename = "Length")]
     pub length: Option<isize>,
+    #[serde(rename = "Data", skip)]
+    pub data: Option<Bytes>,
 }
 
 impl ApiStorageWriteAtRequest {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_path(mut self, path: String) -> Self {
-        self.path = Some(path);
+    pub fn with_path(mut self, path: impl Into<RemotePath>) -> Self {
+        self.path = Some(path.into());
         self
     }
-    pub fn with_compressor(mut self, compressor: String) -> Self {
+    pub fn with_compressor(mut self, compressor: Compressor) -> Self {
         self.compressor = Some(compressor);
         self
     }
@@ -40,6 +45,10 @@ impl ApiStorageWriteAtRequest {
         self.length = Some(length);
         self
     }
+    pub fn with_data(mut self, data: Bytes) -> Self {
+        self.data = Some(data);
+        self
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -56,10 +65,10 @@ impl HttpBuilder for ApiStorageWriteAtRequest {
             let request_fn: RequestFn = Box::new(move || {
                 let mut queries = HashMap::new();
                 if let Some(ref path) = self.path {
-                    queries.insert("Path".to_string(), path.clone());
+                    queries.insert("Path".to_string(), path.to_string());
                 }
-                if let Some(ref compressor) = self.compressor {
-                    queries.insert("Compressor".to_string(), compressor.clone());
+                if let Some(compressor) = self.compressor {
+                    queries.insert("Compressor".to_string(), compressor.to_string());
                 }
                 if let Some(offset) = self.offset {
                     queries.insert("Offset".to_string(), offset.to_string());
@@ -67,12 +76,29 @@ impl HttpBuilder for ApiStorageWriteAtRequest {
                 if let Some(length) = self.length {
                     queries.insert("Length".to_string(), length.to_string());
                 }
-                BaseRequest {
-                    method: Method::POST,
-                    uri: "/api/storage/writeAt".to_string(),
-                    queries: Some(queries),
-                    body: Bytes::from(serde_json::to_string(&self).unwrap()),
-                    ..Default::default()
+                let body = match self.data {
+                    Some(ref data) => match self.compressor {
+                        Some(compressor) if compressor != Compressor::None => {
+                            compressor.compress(data).map(Bytes::from)
+                        }
+                        _ => Ok(data.clone()),
+                    },
+                    None => Ok(Bytes::from(serde_json::to_string(&self).unwrap())),
+                };
+                match body {
+                    Ok(body) => BaseRequest {
+                        method: Method::POST,
+                        uri: "/api/storage/writeAt".to_string(),
+                        queries: Some(queries),
+                        body,
+                        ..Default::default()
+                    },
+                    Err(err) => BaseRequest {
+                        error: Some(Arc::new(
+                            anyhow::Error::new(err).context("failed to compress writeAt data"),
+                        )),
+                        ..Default::default()
+                    },
                 }
             });
             let response_fn: AsyncResponseFn<Self::Response> =
@@ -94,11 +120,11 @@ mod tests {
         tracing_subscriber::fmt::init();
         dotenvy::dotenv()?;
         let config = OpenApiConfig::new().load_from_env()?;
-        let user_id = config.user_id.clone();
+        let home = RemotePath::home(&config)?;
         let mut client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
 
         let http_fn = ApiStorageWriteAtRequest::new()
-            .with_path(format!("/{}/runner.py", user_id))
+            .with_path(home.join("runner.py")?)
             .builder();
         let response = client.send(http_fn).await?;
         info!("response: {:#?}", response);
@@ -1,7 +1,11 @@
 pub mod client;
+pub mod compressor;
 pub mod config;
+pub mod content_disposition;
 pub mod crypt;
 pub mod define;
+pub mod remote_path;
 pub mod request;
 pub mod signer;
+pub mod throttle;
 pub mod time;
@@ -1,18 +1,28 @@
 use crate::common::config::{EndpointType, OpenApiConfig};
-use crate::common::define::{BaseRequest, HttpFn};
+use crate::common::define::{BaseRequest, BytesStream, HttpFn};
 use crate::common::request::HttpBuilder;
 use crate::common::signer::Signer;
+use crate::common::throttle::BandwidthLimiter;
 use crate::common::time::current_timestamp;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
 
-#[derive(Debug, Default)]
+/// Notified of every request a client has sent, after its response arrives and
+/// before it is parsed.
+pub trait RequestObserver: std::fmt::Debug + Send + Sync {
+    fn on_request_sent(&self, request: &BaseRequest);
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct OpenApiClient {
     config: OpenApiConfig,
     signer: Signer,
 
     endpoint_type: EndpointType,
+    bandwidth_limiter: Option<BandwidthLimiter>,
+    request_observers: Vec<Arc<dyn RequestObserver>>,
 }
 
 impl OpenApiClient {
@@ -31,6 +41,29 @@ impl OpenApiClient {
         self
     }
 
+    /// Caps request bodies sent by this client and, through [`OpenApiClient::throttle`],
+    /// the download streams read with it.
+    pub fn with_bandwidth_limiter(mut self, bandwidth_limiter: BandwidthLimiter) -> Self {
+        self.bandwidth_limiter = Some(bandwidth_limiter);
+        self
+    }
+
+    pub fn bandwidth_limiter(&self) -> Option<&BandwidthLimiter> {
+        self.bandwidth_limiter.as_ref()
+    }
+
+    pub fn with_request_observer(mut self, request_observer: Arc<dyn RequestObserver>) -> Self {
+        self.request_observers.push(request_observer);
+        self
+    }
+
+    pub fn throttle(&self, stream: BytesStream) -> BytesStream {
+        match &self.bandwidth_limiter {
+            Some(bandwidth_limiter) => bandwidth_limiter.throttle(stream),
+            None => stream,
+        }
+    }
+
     pub async fn send<R>(&mut self, http_fn: HttpFn<R>) -> anyhow::Result<R>
     where
         R: std::fmt::Debug + Send + 'static,
@@ -41,6 +74,7 @@ impl OpenApiClient {
         dbg!(&base_request);
 
         self.default_headers_queries(&mut base_request)?;
+        let observed_request = (!self.request_observers.is_empty()).then(|| base_request.clone());
 
         let endpoint = match self.endpoint_type {
             EndpointType::Api => self.config.endpoint.clone(),
@@ -52,12 +86,19 @@ impl OpenApiClient {
         let response = HttpBuilder::new()
             .with_base_url(endpoint)
             .with_base_request(base_request)
+            .with_bandwidth_limiter(self.bandwidth_limiter.clone())
             .builder()?
             .send()
             .await?;
 
         dbg!(&response);
 
+        if let Some(request) = observed_request {
+            for request_observer in &self.request_observers {
+                request_observer.on_request_sent(&request);
+            }
+        }
+
         resp_fn(response).await
     }
 
@@ -0,0 +1,150 @@
+use crate::common::define::BytesStream;
+use async_compression::tokio::bufread::{
+    GzipDecoder, GzipEncoder, Lz4Decoder, Lz4Encoder, ZstdDecoder, ZstdEncoder,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Payload compression understood by the `Compressor` parameter of `readAt` and `writeAt`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Compressor {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "zstd")]
+    Zstd,
+    #[serde(rename = "lz4")]
+    Lz4,
+}
+
+impl Compressor {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compressor::None => "none",
+            Compressor::Gzip => "gzip",
+            Compressor::Zstd => "zstd",
+            Compressor::Lz4 => "lz4",
+        }
+    }
+
+    /// Compresses a buffer with the same codec as the streaming paths, so
+    /// every path agrees on the framing.
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        read_in_memory(self.encoder(data))
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        read_in_memory(self.decoder(data))
+    }
+
+    pub fn decompress_stream(&self, stream: BytesStream) -> BytesStream {
+        Box::pin(ReaderStream::new(self.decoder(StreamReader::new(stream))))
+    }
+
+    fn encoder<'a>(
+        &self,
+        reader: impl AsyncBufRead + Unpin + Send + 'a,
+    ) -> Box<dyn AsyncRead + Unpin + Send + 'a> {
+        match self {
+            Compressor::None => Box::new(reader),
+            Compressor::Gzip => Box::new(GzipEncoder::new(reader)),
+            Compressor::Zstd => Box::new(ZstdEncoder::new(reader)),
+            Compressor::Lz4 => Box::new(Lz4Encoder::new(reader)),
+        }
+    }
+
+    fn decoder<'a>(
+        &self,
+        reader: impl AsyncBufRead + Unpin + Send + 'a,
+    ) -> Box<dyn AsyncRead + Unpin + Send + 'a> {
+        match self {
+            Compressor::None => Box::new(reader),
+            Compressor::Gzip => Box::new(GzipDecoder::new(reader)),
+            Compressor::Zstd => Box::new(ZstdDecoder::new(reader)),
+            Compressor::Lz4 => Box::new(Lz4Decoder::new(reader)),
+        }
+    }
+}
+
+/// Reads a reader over an in-memory buffer to the end. It never waits for
+/// I/O, so it is driven in place without a runtime.
+fn read_in_memory(mut reader: impl AsyncRead + Unpin) -> io::Result<Vec<u8>> {
+    futures::executor::block_on(async {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        Ok(data)
+    })
+}
+
+impl fmt::Display for Compressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Compressor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "" | "none" => Ok(Compressor::None),
+            "gzip" => Ok(Compressor::Gzip),
+            "zstd" => Ok(Compressor::Zstd),
+            "lz4" => Ok(Compressor::Lz4),
+            _ => Err(anyhow::anyhow!("unknown compressor: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::{StreamExt, stream};
+
+    const COMPRESSORS: [Compressor; 4] = [
+        Compressor::None,
+        Compressor::Gzip,
+        Compressor::Zstd,
+        Compressor::Lz4,
+    ];
+
+    #[test]
+    fn test_compress() -> anyhow::Result<()> {
+        let data = "step 1 residual 0.001\n".repeat(1000).into_bytes();
+        for compressor in COMPRESSORS {
+            let compressed = compressor.compress(&data)?;
+            if compressor != Compressor::None {
+                assert!(compressed.len() < data.len() / 10, "{}", compressor);
+            }
+            assert_eq!(compressor.decompress(&compressed)?, data, "{}", compressor);
+            assert_eq!(compressor.as_str().parse::<Compressor>()?, compressor);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_decompress_stream() -> anyhow::Result<()> {
+        let data = "step 1 residual 0.001\n".repeat(1000).into_bytes();
+        for compressor in COMPRESSORS {
+            let compressed = compressor.compress(&data)?;
+            let chunks: Vec<std::io::Result<Bytes>> = compressed
+                .chunks(100)
+                .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+                .collect();
+            let mut stream = compressor.decompress_stream(Box::pin(stream::iter(chunks)));
+            let mut decompressed = Vec::new();
+            while let Some(data) = stream.next().await {
+                decompressed.extend_from_slice(&data?);
+            }
+            assert_eq!(decompressed, data, "{}", compressor);
+        }
+        Ok(())
+    }
+}
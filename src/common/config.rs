@@ -1,6 +1,6 @@
 use std::env;
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct OpenApiConfig {
     pub app_key: String,
     pub app_secret: String,
@@ -12,7 +12,7 @@ pub struct OpenApiConfig {
     pub zone: String,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum EndpointType {
     #[default]
     Api,
@@ -0,0 +1,223 @@
+use anyhow::{anyhow, bail};
+use percent_encoding::percent_decode_str;
+
+/// A parsed `Content-Disposition` header (RFC 6266).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContentDisposition {
+    /// The lowercased disposition type, e.g. `attachment` or `inline`.
+    pub disposition_type: String,
+    /// Parameters in header order with lowercased names. Extended `name*`
+    /// values (RFC 5987) are stored decoded under their `name*` key.
+    pub params: Vec<(String, String)>,
+}
+
+impl ContentDisposition {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            input: value,
+            pos: 0,
+        };
+        let disposition_type = parser.token();
+        if disposition_type.is_empty() {
+            bail!("missing disposition type in {:?}", value);
+        }
+        let mut content_disposition = Self {
+            disposition_type: disposition_type.to_ascii_lowercase(),
+            params: Vec::new(),
+        };
+
+        loop {
+            parser.skip_whitespace();
+            if parser.is_empty() {
+                break;
+            }
+            parser.expect(';', value)?;
+            parser.skip_whitespace();
+            // tolerate a trailing or doubled `;`
+            if parser.is_empty() || parser.peek() == Some(';') {
+                continue;
+            }
+            let name = parser.token().to_ascii_lowercase();
+            if name.is_empty() {
+                bail!("missing parameter name in {:?}", value);
+            }
+            parser.skip_whitespace();
+            parser.expect('=', value)?;
+            parser.skip_whitespace();
+            let raw = if parser.peek() == Some('"') {
+                parser.quoted_string(value)?
+            } else {
+                parser.token().to_string()
+            };
+            let param = if name.ends_with('*') {
+                decode_ext_value(&raw)?
+            } else {
+                raw
+            };
+            content_disposition.params.push((name, param));
+        }
+
+        Ok(content_disposition)
+    }
+
+    /// The value of parameter `name` (case-insensitive), preferring the
+    /// extended `name*` form when both are present.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        let extended = format!("{}*", name);
+        self.find(&extended).or_else(|| self.find(&name))
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.param("filename")
+    }
+
+    pub fn is_attachment(&self) -> bool {
+        self.disposition_type == "attachment"
+    }
+
+    fn find(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char, value: &str) -> anyhow::Result<()> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(c) => bail!(
+                "expected {:?} but found {:?} at {} in {:?}",
+                expected,
+                c,
+                self.pos,
+                value
+            ),
+            None => bail!("expected {:?} at the end of {:?}", expected, value),
+        }
+    }
+
+    /// Everything up to the next separator. Wider than the RFC token so that
+    /// unquoted non-ASCII file names sent by lenient servers still parse.
+    fn token(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self
+            .peek()
+            .filter(|c| !c.is_ascii_whitespace() && !matches!(c, ';' | '=' | '"'))
+        {
+            self.pos += c.len_utf8();
+        }
+        &self.input[start..self.pos]
+    }
+
+    fn quoted_string(&mut self, value: &str) -> anyhow::Result<String> {
+        self.expect('"', value)?;
+        let mut quoted = String::new();
+        let mut chars = self.input[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(quoted);
+                }
+                '\\' => {
+                    let (_, escaped) = chars
+                        .next()
+                        .ok_or_else(|| anyhow!("unterminated escape in {:?}", value))?;
+                    quoted.push(escaped);
+                }
+                c => quoted.push(c),
+            }
+        }
+        bail!("unterminated quoted string in {:?}", value)
+    }
+}
+
+/// Decodes an RFC 5987 `charset'language'percent-encoded` value.
+fn decode_ext_value(raw: &str) -> anyhow::Result<String> {
+    let mut parts = raw.splitn(3, '\'');
+    let (Some(charset), Some(_language), Some(encoded)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        bail!("invalid extended parameter value {:?}", raw);
+    };
+    let bytes: Vec<u8> = percent_decode_str(encoded).collect();
+    match charset.to_ascii_lowercase().as_str() {
+        "utf-8" => Ok(String::from_utf8(bytes)?),
+        "iso-8859-1" => Ok(bytes.into_iter().map(char::from).collect()),
+        charset => bail!("unsupported charset {:?} in {:?}", charset, raw),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filename() {
+        let cd = ContentDisposition::parse(r#"attachment; filename="runner.py""#).unwrap();
+        assert!(cd.is_attachment());
+        assert_eq!(cd.filename(), Some("runner.py"));
+
+        let cd = ContentDisposition::parse("Attachment;FileName=plain.txt;").unwrap();
+        assert!(cd.is_attachment());
+        assert_eq!(cd.filename(), Some("plain.txt"));
+
+        let cd =
+            ContentDisposition::parse(r#"inline; filename="a \"quoted\" name; x.txt""#).unwrap();
+        assert!(!cd.is_attachment());
+        assert_eq!(cd.filename(), Some(r#"a "quoted" name; x.txt"#));
+
+        assert_eq!(
+            ContentDisposition::parse("inline").unwrap().filename(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_extended_filename() {
+        let cd = ContentDisposition::parse(
+            r#"attachment; filename="EURO rates.txt"; filename*=UTF-8''%E2%82%AC%20rates.txt"#,
+        )
+        .unwrap();
+        assert_eq!(cd.filename(), Some("€ rates.txt"));
+        assert_eq!(cd.param("FILENAME"), Some("€ rates.txt"));
+
+        let cd =
+            ContentDisposition::parse("attachment; filename*=iso-8859-1'en'%A3%20rates").unwrap();
+        assert_eq!(cd.filename(), Some("£ rates"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(ContentDisposition::parse("").is_err());
+        assert!(ContentDisposition::parse(r#"attachment; filename="open"#).is_err());
+        assert!(ContentDisposition::parse("attachment; filename").is_err());
+        assert!(ContentDisposition::parse("attachment; filename*=no-quotes").is_err());
+        assert!(ContentDisposition::parse("attachment; filename*=UTF-8''%FF").is_err());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod md5;
+pub mod rolling;
 pub mod sha1;
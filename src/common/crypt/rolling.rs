@@ -0,0 +1,305 @@
+//! Client-side counterparts of the checksums returned by `/api/storage/checksum`.
+//!
+//! The API does not document its algorithms. `Rsync` follows librsync's
+//! rollsum (`ROLLSUM_CHAR_OFFSET` 31, digest `s2 << 16 | s1`, stored big-endian
+//! as in librsync signatures), `Adler32` follows RFC 1950 and the strong
+//! checksum is MD5, with `RollingHashType` numbered 0 and 1 in that order.
+//! `test_server_checksums` compares all of this with the checksums of a file
+//! uploaded to the server configured in `.env`; until it has passed there,
+//! callers should confirm weak matches with the strong checksum and treat a
+//! mismatch as a changed block.
+
+use crate::model::file::ChunkChecksum;
+use md5::{Digest, Md5};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const ADLER32_MOD: u32 = 65521;
+const RSYNC_CHAR_OFFSET: u32 = 31;
+
+/// Weak rolling checksum algorithms, assumed to be numbered as in the
+/// `RollingHashType` parameter of the checksum APIs (0 and 1).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RollingHashType {
+    #[default]
+    Rsync,
+    Adler32,
+}
+
+impl TryFrom<isize> for RollingHashType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: isize) -> anyhow::Result<Self> {
+        match value {
+            0 => Ok(RollingHashType::Rsync),
+            1 => Ok(RollingHashType::Adler32),
+            _ => Err(anyhow::anyhow!("unknown rolling hash type: {}", value)),
+        }
+    }
+}
+
+impl From<RollingHashType> for isize {
+    fn from(value: RollingHashType) -> Self {
+        match value {
+            RollingHashType::Rsync => 0,
+            RollingHashType::Adler32 => 1,
+        }
+    }
+}
+
+/// A weak checksum over a sliding window that can be advanced one byte at a time.
+#[derive(Debug, Clone)]
+pub struct RollingChecksum {
+    hash_type: RollingHashType,
+    s1: u32,
+    s2: u32,
+    count: u32,
+}
+
+impl RollingChecksum {
+    pub fn new(hash_type: RollingHashType) -> Self {
+        let s1 = match hash_type {
+            RollingHashType::Rsync => 0,
+            RollingHashType::Adler32 => 1,
+        };
+        Self {
+            hash_type,
+            s1,
+            s2: 0,
+            count: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self.hash_type {
+            RollingHashType::Rsync => {
+                for &byte in data {
+                    self.s1 = self.s1.wrapping_add(byte as u32 + RSYNC_CHAR_OFFSET);
+                    self.s2 = self.s2.wrapping_add(self.s1);
+                }
+            }
+            RollingHashType::Adler32 => {
+                for &byte in data {
+                    self.s1 = (self.s1 + byte as u32) % ADLER32_MOD;
+                    self.s2 = (self.s2 + self.s1) % ADLER32_MOD;
+                }
+            }
+        }
+        self.count = self.count.wrapping_add(data.len() as u32);
+    }
+
+    /// Slides the window one byte: `out` leaves at the front, `input` enters at the back.
+    pub fn roll(&mut self, out: u8, input: u8) {
+        match self.hash_type {
+            RollingHashType::Rsync => {
+                self.s1 = self.s1.wrapping_add(input as u32).wrapping_sub(out as u32);
+                self.s2 = self
+                    .s2
+                    .wrapping_add(self.s1)
+                    .wrapping_sub(self.count.wrapping_mul(out as u32 + RSYNC_CHAR_OFFSET));
+            }
+            RollingHashType::Adler32 => {
+                let count = self.count % ADLER32_MOD;
+                self.s1 = (self.s1 + ADLER32_MOD - out as u32 + input as u32) % ADLER32_MOD;
+                self.s2 = (self.s2 + ADLER32_MOD - (count * out as u32) % ADLER32_MOD
+                    + self.s1
+                    + ADLER32_MOD
+                    - 1)
+                    % ADLER32_MOD;
+            }
+        }
+    }
+
+    pub fn digest(&self) -> u32 {
+        (self.s2 << 16) | (self.s1 & 0xffff)
+    }
+
+    pub fn digest_bytes(&self) -> Vec<u8> {
+        self.digest().to_be_bytes().to_vec()
+    }
+}
+
+pub fn weak_checksum(hash_type: RollingHashType, data: &[u8]) -> Vec<u8> {
+    let mut checksum = RollingChecksum::new(hash_type);
+    checksum.update(data);
+    checksum.digest_bytes()
+}
+
+pub fn strong_checksum(data: &[u8]) -> Vec<u8> {
+    Md5::digest(data).to_vec()
+}
+
+/// Reads `reader` to the end and returns the weak and strong checksum of every
+/// `block_size` block, in the same shape as the checksum API.
+pub async fn chunk_checksums<R>(
+    mut reader: R,
+    block_size: usize,
+    hash_type: RollingHashType,
+) -> std::io::Result<Vec<ChunkChecksum>>
+where
+    R: AsyncRead + Unpin,
+{
+    let block_size = block_size.max(1);
+    let mut checksums = Vec::new();
+    let mut buffer = vec![0u8; block_size];
+    let mut offset = 0;
+    loop {
+        let mut filled = 0;
+        while filled < block_size {
+            let n = reader.read(&mut buffer[filled..]).await?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        if filled == 0 {
+            break;
+        }
+        let block = &buffer[..filled];
+        checksums.push(ChunkChecksum {
+            chunk_offset: offset as isize,
+            size: filled as isize,
+            weak_checksum: weak_checksum(hash_type, block),
+            strong_checksum: strong_checksum(block),
+        });
+        offset += filled;
+        if filled < block_size {
+            break;
+        }
+    }
+    Ok(checksums)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference values from RFC 1950 implementations (zlib `adler32`).
+    #[test]
+    fn test_adler32() {
+        let digest = |data: &[u8]| {
+            let mut checksum = RollingChecksum::new(RollingHashType::Adler32);
+            checksum.update(data);
+            checksum.digest()
+        };
+        assert_eq!(digest(b""), 0x00000001);
+        assert_eq!(digest(b"a"), 0x00620062);
+        assert_eq!(digest(b"abc"), 0x024d0127);
+        assert_eq!(digest(b"Wikipedia"), 0x11e60398);
+        assert_eq!(digest(b"message digest"), 0x29750586);
+    }
+
+    // Derived by hand from librsync's rollsum: for "abc", s1 = 128 + 129 + 130
+    // = 0x183 and s2 = 128 + 257 + 387 = 0x304.
+    #[test]
+    fn test_rsync() {
+        assert_eq!(
+            weak_checksum(RollingHashType::Rsync, b""),
+            vec![0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            weak_checksum(RollingHashType::Rsync, b"abc"),
+            vec![0x03, 0x04, 0x01, 0x83]
+        );
+    }
+
+    #[test]
+    fn test_roll() {
+        let data: Vec<u8> = (0..4096u32).map(|i| (i * 7919 % 251) as u8).collect();
+        for hash_type in [RollingHashType::Rsync, RollingHashType::Adler32] {
+            let window = 64;
+            let mut rolling = RollingChecksum::new(hash_type);
+            rolling.update(&data[..window]);
+            for start in 1..data.len() - window {
+                rolling.roll(data[start - 1], data[start + window - 1]);
+                assert_eq!(
+                    rolling.digest_bytes(),
+                    weak_checksum(hash_type, &data[start..start + window]),
+                    "{:?} window at {}",
+                    hash_type,
+                    start
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rolling_hash_type() {
+        for hash_type in [RollingHashType::Rsync, RollingHashType::Adler32] {
+            assert_eq!(
+                RollingHashType::try_from(isize::from(hash_type)).unwrap(),
+                hash_type
+            );
+        }
+        assert!(RollingHashType::try_from(7).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chunk_checksums() -> anyhow::Result<()> {
+        let data = b"The quick brown fox jumps over the lazy dog";
+        let checksums = chunk_checksums(&data[..], 16, RollingHashType::Adler32).await?;
+        assert_eq!(checksums.len(), 3);
+        assert_eq!(checksums[2].chunk_offset, 32);
+        assert_eq!(checksums[2].size, 11);
+        assert_eq!(
+            hex::encode(&checksums[0].strong_checksum),
+            hex::encode(strong_checksum(&data[..16]))
+        );
+        assert_eq!(
+            checksums[1].weak_checksum,
+            weak_checksum(RollingHashType::Adler32, &data[16..32])
+        );
+        assert!(
+            chunk_checksums(&b""[..], 16, RollingHashType::Rsync)
+                .await?
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_server_checksums() -> anyhow::Result<()> {
+        use crate::common::client::OpenApiClient;
+        use crate::common::config::{EndpointType, OpenApiConfig};
+        use crate::common::remote_path::RemotePath;
+        use crate::storage::fs;
+
+        dotenvy::dotenv()?;
+        let config = OpenApiConfig::new().load_from_env()?;
+        let path = RemotePath::home(&config)?
+            .join("rolling-checksums.bin")?
+            .to_string();
+        let client = OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud);
+
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7919 % 251) as u8).collect();
+        fs::upload(&client, &path, data.clone(), true).await?;
+        let compared = async {
+            for hash_type in [RollingHashType::Rsync, RollingHashType::Adler32] {
+                let remote =
+                    fs::chunk_checksums(&client, &path, 4096, Some(hash_type.into())).await?;
+                let local = chunk_checksums(&data[..], 4096, hash_type).await?;
+                assert_eq!(remote.len(), local.len(), "{:?}", hash_type);
+                for (remote, local) in remote.iter().zip(&local) {
+                    assert_eq!(remote.chunk_offset, local.chunk_offset);
+                    assert_eq!(remote.size, local.size);
+                    assert_eq!(
+                        hex::encode(&remote.weak_checksum),
+                        hex::encode(&local.weak_checksum),
+                        "{:?} weak checksum at {}",
+                        hash_type,
+                        local.chunk_offset
+                    );
+                    assert_eq!(
+                        hex::encode(&remote.strong_checksum),
+                        hex::encode(&local.strong_checksum),
+                        "strong checksum at {}",
+                        local.chunk_offset
+                    );
+                }
+            }
+            anyhow::Ok(())
+        }
+        .await;
+        fs::remove(&client, &path).await?;
+        compared
+    }
+}
@@ -4,8 +4,9 @@ use reqwest::header::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::Arc;
 
-pub type BytesStream = Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>;
+pub type BytesStream = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
 
 pub trait HttpBuilder {
     type Response;
@@ -27,7 +28,7 @@ pub type AsyncResponseFn<T> = Box<
         + Sync,
 >;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct BaseRequest {
     pub method: reqwest::Method,
     pub uri: String,
@@ -38,6 +39,9 @@ pub struct BaseRequest {
     pub queries: Option<HashMap<String, String>>,
     pub form: Option<HashMap<String, String>>,
     pub body: Bytes,
+
+    /// Set when the request could not be built; `send` returns it instead of sending.
+    pub error: Option<Arc<anyhow::Error>>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -52,3 +56,17 @@ pub struct BaseResponse<T> {
     #[serde(rename = "Data")]
     pub data: Option<T>,
 }
+
+impl<T> BaseResponse<T> {
+    pub fn into_data(self) -> anyhow::Result<Option<T>> {
+        if !self.error_code.is_empty() {
+            anyhow::bail!(
+                "request {} failed: {} {}",
+                self.request_id,
+                self.error_code,
+                self.error_msg
+            );
+        }
+        Ok(self.data)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::common::config::OpenApiConfig;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A normalized absolute storage path such as `/<user_id>/dir/file`.
+///
+/// Backslashes become `/`, empty and `.` segments are dropped and `..` is
+/// resolved. A `..` that would leave the first segment, the user root, is
+/// rejected, as is a relative path. `~` is only accepted by
+/// [`RemotePath::resolve`], which expands it to `/<user_id>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RemotePath(String);
+
+impl RemotePath {
+    pub fn new(path: impl AsRef<str>) -> anyhow::Result<Self> {
+        normalize(path.as_ref(), None)
+    }
+
+    /// Like [`RemotePath::new`], but expands a leading `~` to `/<user_id>`.
+    pub fn resolve(path: impl AsRef<str>, config: &OpenApiConfig) -> anyhow::Result<Self> {
+        normalize(path.as_ref(), Some(&config.user_id))
+    }
+
+    /// The user root `/<user_id>`.
+    pub fn home(config: &OpenApiConfig) -> anyhow::Result<Self> {
+        Self::resolve("~", config)
+    }
+
+    /// Appends the relative `path`, which may contain several segments.
+    pub fn join(&self, path: impl AsRef<str>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.starts_with(['/', '\\', '~']) {
+            bail!("cannot join {} onto {}: not a relative path", path, self);
+        }
+        Self::new(format!("{}/{}", self.0, path))
+    }
+
+    /// The containing directory, `None` for `/`.
+    pub fn parent(&self) -> Option<Self> {
+        let (parent, _) = self.0.rsplit_once('/')?;
+        match parent {
+            "" if self.0 == "/" => None,
+            "" => Some(Self("/".to_string())),
+            parent => Some(Self(parent.to_string())),
+        }
+    }
+
+    /// The last segment, `None` for `/`.
+    pub fn file_name(&self) -> Option<&str> {
+        self.0.rsplit('/').next().filter(|name| !name.is_empty())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn normalize(path: &str, user_id: Option<&str>) -> anyhow::Result<RemotePath> {
+    let path = path.replace('\\', "/");
+    let mut segments: Vec<&str> = Vec::new();
+    let rest = if path == "~" || path.starts_with("~/") {
+        let Some(user_id) = user_id else {
+            bail!(
+                "{} is relative to the user root, use RemotePath::resolve",
+                path
+            );
+        };
+        if user_id.is_empty() || user_id.contains(['/', '\\']) || user_id == ".." {
+            bail!("invalid user id {:?} for {}", user_id, path);
+        }
+        segments.push(user_id);
+        &path[1..]
+    } else if path.starts_with('/') {
+        &path[..]
+    } else {
+        bail!("{:?} is not an absolute path", path);
+    };
+
+    for segment in rest.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.len() <= 1 {
+                    bail!("{} escapes the user root", path);
+                }
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(RemotePath(format!("/{}", segments.join("/"))))
+}
+
+impl fmt::Display for RemotePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for RemotePath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for RemotePath {
+    type Err = anyhow::Error;
+
+    fn from_str(path: &str) -> anyhow::Result<Self> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<&str> for RemotePath {
+    type Error = anyhow::Error;
+
+    fn try_from(path: &str) -> anyhow::Result<Self> {
+        Self::new(path)
+    }
+}
+
+impl TryFrom<String> for RemotePath {
+    type Error = anyhow::Error;
+
+    fn try_from(path: String) -> anyhow::Result<Self> {
+        Self::new(path)
+    }
+}
+
+impl From<&RemotePath> for RemotePath {
+    fn from(path: &RemotePath) -> Self {
+        path.clone()
+    }
+}
+
+impl From<RemotePath> for String {
+    fn from(path: RemotePath) -> Self {
+        path.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(RemotePath::new("/u//a/./b/").unwrap().as_str(), "/u/a/b");
+        assert_eq!(RemotePath::new("\\u\\a\\..\\b").unwrap().as_str(), "/u/b");
+        assert_eq!(RemotePath::new("/").unwrap().as_str(), "/");
+        assert!(RemotePath::new("/u/..").is_err());
+        assert!(RemotePath::new("/u/a/../../v").is_err());
+        assert!(RemotePath::new("u/a").is_err());
+        assert!(RemotePath::new("~/a").is_err());
+    }
+
+    #[test]
+    fn test_resolve_home() {
+        let config = OpenApiConfig::new().with_user_id("u1".to_string());
+        let home = RemotePath::home(&config).unwrap();
+        assert_eq!(home.as_str(), "/u1");
+        assert_eq!(
+            RemotePath::resolve("~/a/../b", &config).unwrap().as_str(),
+            "/u1/b"
+        );
+        assert!(RemotePath::resolve("~/..", &config).is_err());
+        assert!(RemotePath::resolve("~", &OpenApiConfig::new()).is_err());
+    }
+
+    #[test]
+    fn test_join_and_parts() {
+        let dir = RemotePath::new("/u1/dir").unwrap();
+        let file = dir.join("sub/file.txt").unwrap();
+        assert_eq!(file.as_str(), "/u1/dir/sub/file.txt");
+        assert_eq!(file.file_name(), Some("file.txt"));
+        assert_eq!(file.parent().unwrap().as_str(), "/u1/dir/sub");
+        assert_eq!(
+            RemotePath::new("/u1").unwrap().parent().unwrap().as_str(),
+            "/"
+        );
+        assert_eq!(RemotePath::new("/").unwrap().parent(), None);
+        assert!(dir.join("../../x").is_err());
+        assert!(dir.join("/x").is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        let path: RemotePath = serde_json::from_str(r#""/u1/a//b""#).unwrap();
+        assert_eq!(serde_json::to_string(&path).unwrap(), r#""/u1/a/b""#);
+        assert!(serde_json::from_str::<RemotePath>(r#""a/b""#).is_err());
+    }
+}
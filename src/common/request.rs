@@ -1,6 +1,12 @@
 use crate::common::define::BaseRequest;
+use crate::common::throttle::BandwidthLimiter;
 use anyhow::anyhow;
-use reqwest::{Client, Method, RequestBuilder};
+use bytes::Bytes;
+use futures::stream;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{Body, Client, Method, RequestBuilder};
+
+const THROTTLED_CHUNK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Default)]
 pub struct HttpBuilder {
@@ -8,6 +14,7 @@ pub struct HttpBuilder {
     pub base_url: String,
 
     pub base_request: BaseRequest,
+    pub bandwidth_limiter: Option<BandwidthLimiter>,
 }
 
 impl HttpBuilder {
@@ -25,7 +32,15 @@ impl HttpBuilder {
         self
     }
 
+    pub fn with_bandwidth_limiter(mut self, bandwidth_limiter: Option<BandwidthLimiter>) -> Self {
+        self.bandwidth_limiter = bandwidth_limiter;
+        self
+    }
+
     pub fn builder(self) -> anyhow::Result<RequestBuilder> {
+        if let Some(error) = self.base_request.error {
+            return Err(anyhow!("failed to build request: {:#}", error));
+        }
         let url = format!(
             "{}{}?{}",
             self.base_url,
@@ -41,8 +56,44 @@ impl HttpBuilder {
             Method::DELETE => self.http_client.delete(&url),
             _ => Err(anyhow!("unsupported method"))?,
         };
-        Ok(request_builder
-            .headers(self.base_request.headers.clone())
-            .body(self.base_request.body))
+        let request_builder = request_builder.headers(self.base_request.headers.clone());
+        let body = self.base_request.body;
+        Ok(match self.bandwidth_limiter {
+            Some(bandwidth_limiter) if !body.is_empty() => {
+                let content_length = body.len();
+                let chunks: Vec<std::io::Result<Bytes>> = (0..body.len())
+                    .step_by(THROTTLED_CHUNK_SIZE)
+                    .map(|start| {
+                        Ok(body.slice(start..(start + THROTTLED_CHUNK_SIZE).min(body.len())))
+                    })
+                    .collect();
+                request_builder
+                    .header(CONTENT_LENGTH, content_length)
+                    .body(Body::wrap_stream(
+                        bandwidth_limiter.throttle(Box::pin(stream::iter(chunks))),
+                    ))
+            }
+            _ => request_builder.body(body),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_builder_returns_request_error() {
+        let result = HttpBuilder::new()
+            .with_base_url("http://localhost".to_string())
+            .with_base_request(BaseRequest {
+                method: Method::POST,
+                error: Some(Arc::new(anyhow!("compress failed"))),
+                ..Default::default()
+            })
+            .builder();
+        let err = result.expect_err("a request with an error must not be built");
+        assert!(err.to_string().contains("compress failed"));
     }
 }
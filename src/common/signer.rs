@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use std::fmt::Write;
 use std::str::from_utf8;
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Signer {
     pub app_key: String,
     pub app_secret: String,
@@ -20,6 +20,7 @@ impl Signer {
         }
     }
 
+    #[allow(clippy::collapsible_if)]
     pub fn sign_request(
         &self,
         base_request: &BaseRequest,
@@ -0,0 +1,133 @@
+use crate::common::define::BytesStream;
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A token bucket limiting throughput to a number of bytes per second.
+///
+/// Clones share the same bucket, so one limiter can cap many concurrent
+/// transfers. A child limiter applies its own rate and its parent's, which
+/// allows a per-transfer limit below a per-client one. The rate can be changed
+/// at any time with [`BandwidthLimiter::set_rate`].
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    parent: Option<Box<BandwidthLimiter>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: Option<u64>,
+    available: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn reserve(&mut self, bytes: u64) -> Duration {
+        let now = Instant::now();
+        let Some(rate) = self.rate.filter(|rate| *rate > 0) else {
+            self.updated = now;
+            return Duration::ZERO;
+        };
+        let rate = rate as f64;
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * rate).min(rate);
+        self.updated = now;
+        self.available -= bytes as f64;
+        if self.available < 0.0 {
+            Duration::from_secs_f64(-self.available / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+impl BandwidthLimiter {
+    /// Creates a limiter allowing `rate` bytes per second, `None` meaning unlimited.
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate,
+                available: rate.unwrap_or_default() as f64,
+                updated: Instant::now(),
+            })),
+            parent: None,
+        }
+    }
+
+    /// Creates a limiter that is also bound by this one.
+    pub fn child(&self, rate: Option<u64>) -> Self {
+        Self {
+            parent: Some(Box::new(self.clone())),
+            ..Self::new(rate)
+        }
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket.lock().unwrap().rate
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.rate = rate;
+        bucket.available = bucket.available.min(rate.unwrap_or_default() as f64);
+    }
+
+    /// Waits until `bytes` may be transferred under this limiter and its parents.
+    pub async fn acquire(&self, bytes: u64) {
+        let mut wait = Duration::ZERO;
+        let mut limiter = Some(self);
+        while let Some(current) = limiter {
+            wait = wait.max(current.bucket.lock().unwrap().reserve(bytes));
+            limiter = current.parent.as_deref();
+        }
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Delays every chunk of `stream` so it is consumed at most at this limiter's rate.
+    pub fn throttle(&self, stream: BytesStream) -> BytesStream {
+        let limiter = self.clone();
+        Box::pin(stream.then(move |data| {
+            let limiter = limiter.clone();
+            async move {
+                if let Ok(data) = &data {
+                    limiter.acquire(data.len() as u64).await;
+                }
+                data
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bandwidth_limiter() {
+        let limiter = BandwidthLimiter::new(Some(10_000));
+        let started = Instant::now();
+        limiter.acquire(10_000).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+        limiter.acquire(2_000).await;
+        assert!(started.elapsed() >= Duration::from_millis(150));
+
+        limiter.set_rate(None);
+        let started = Instant::now();
+        limiter.acquire(1_000_000).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_limiter_child() {
+        let client = BandwidthLimiter::new(None);
+        let transfer = client.child(Some(10_000));
+        let started = Instant::now();
+        transfer.acquire(12_000).await;
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(client.rate(), None);
+        assert_eq!(transfer.rate(), Some(10_000));
+    }
+}
@@ -0,0 +1,876 @@
+use crate::api::v1::storage::api_storage_check_sums_find_chunk::{
+    ApiStorageCheckSumsFindChunkRequest,
+    ApiStorageChunkCheckSumsResponse as ApiStorageFindChunksResponse,
+};
+use crate::api::v1::storage::api_storage_chunk_check_sums::ApiStorageChunkCheckSumsResponse;
+use crate::api::v1::storage::api_storage_list::{ApiStorageListRequest, ApiStorageListResponse};
+use crate::api::v1::storage::api_storage_mkdir::{ApiStorageMkDirRequest, ApiStorageMkDirResponse};
+use crate::api::v1::storage::api_storage_move::{ApiStorageMoveRequest, ApiStorageMoveResponse};
+use crate::api::v1::storage::api_storage_remove::{
+    ApiStorageRemoveRequest, ApiStorageRemoveResponse,
+};
+use crate::api::v1::storage::api_storage_stat::ApiStorageStatResponse;
+use crate::api::v1::storage::api_storage_truncate::{
+    ApiStorageTruncateRequest, ApiStorageTruncateResponse,
+};
+use crate::api::v1::storage::api_storage_upload::ApiStorageUploadResponse;
+use crate::api::v1::storage::api_storage_write_at::ApiStorageWriteAtResponse;
+use crate::common::compressor::Compressor;
+use crate::common::crypt::rolling::{
+    RollingChecksum, RollingHashType, chunk_checksums, strong_checksum,
+};
+use crate::common::define::{BaseRequest, BaseResponse};
+use crate::common::remote_path::RemotePath;
+use crate::common::signer::Signer;
+use crate::model::file::{Chunk, FileInfo};
+use crate::storage::range::ByteRange;
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, FromRequest, Request};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use chrono::{DateTime, Utc};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::Metadata;
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_util::io::ReaderStream;
+use tracing::debug;
+
+const USER_ID_HEADER: &str = "x-ys-user-id";
+const DEFAULT_PAGE_SIZE: usize = 1000;
+
+static REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Serves the `/api/storage/*` endpoints used by this crate from a local
+/// directory, so that code written against an [`OpenApiClient`] with
+/// `EndpointType::Cloud` runs offline by pointing `cloud_endpoint` at it.
+///
+/// The remote path `/<user_id>/a/b` maps to `<root>/<user_id>/a/b`; a user's
+/// directory is created on first use and requests must send `x-ys-user-id`
+/// and may only touch the directory of that user. Requests are signed with the
+/// [`Signer`] rules and rejected unless their `AppKey` was registered with
+/// [`StorageEmulator::with_credential`].
+///
+/// [`OpenApiClient`]: crate::common::client::OpenApiClient
+#[derive(Debug, Clone)]
+pub struct StorageEmulator {
+    root: PathBuf,
+    credentials: HashMap<String, String>,
+    verify_signature: bool,
+}
+
+impl StorageEmulator {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            credentials: HashMap::new(),
+            verify_signature: true,
+        }
+    }
+
+    pub fn with_credential(mut self, app_key: &str, app_secret: &str) -> Self {
+        self.credentials
+            .insert(app_key.to_string(), app_secret.to_string());
+        self
+    }
+
+    /// Accepts unsigned requests when disabled.
+    pub fn with_verify_signature(mut self, verify_signature: bool) -> Self {
+        self.verify_signature = verify_signature;
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/api/storage/lsWithPage", get(list))
+            .route("/api/storage/stat", get(stat))
+            .route("/api/storage/mkdir", post(mkdir))
+            .route("/api/storage/mv", post(rename))
+            .route("/api/storage/rm", post(remove))
+            .route("/api/storage/truncate", post(truncate))
+            .route("/api/storage/upload/file", post(upload))
+            .route("/api/storage/download", get(download))
+            .route("/api/storage/readAt", get(read_at))
+            .route("/api/storage/writeAt", post(write_at))
+            .route("/api/storage/checksum", get(checksum))
+            .route("/api/storage/checksumsFindChunks", post(find_chunks))
+            .layer(DefaultBodyLimit::disable())
+            .with_state(Arc::new(self))
+    }
+
+    pub async fn serve(self, listener: TcpListener) -> anyhow::Result<()> {
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    fn verify(
+        &self,
+        queries: &HashMap<String, String>,
+        headers: &HeaderMap,
+        body: &Bytes,
+    ) -> Result<(), ApiError> {
+        let app_key = queries
+            .get("AppKey")
+            .ok_or_else(|| ApiError::unauthorized("missing AppKey"))?;
+        let app_secret = self
+            .credentials
+            .get(app_key)
+            .ok_or_else(|| ApiError::unauthorized(format!("unknown AppKey {}", app_key)))?;
+        let signature = queries
+            .get("Signature")
+            .ok_or_else(|| ApiError::unauthorized("missing Signature"))?;
+        let request = BaseRequest {
+            content_type: headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            body: body.clone(),
+            ..Default::default()
+        };
+        let expected = Signer::new(app_key, app_secret)
+            .sign_request(&request, queries)
+            .map_err(ApiError::invalid)?;
+        if *signature != expected {
+            return Err(ApiError::unauthorized("signature mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// A verified request: its decoded query, caller and body.
+struct Call {
+    emulator: Arc<StorageEmulator>,
+    queries: HashMap<String, String>,
+    user_id: String,
+    body: Bytes,
+}
+
+impl FromRequest<Arc<StorageEmulator>> for Call {
+    type Rejection = ApiError;
+
+    async fn from_request(
+        request: Request,
+        emulator: &Arc<StorageEmulator>,
+    ) -> Result<Self, ApiError> {
+        let (parts, body) = request.into_parts();
+        debug!("{} {}", parts.method, parts.uri);
+        let queries: HashMap<String, String> =
+            serde_urlencoded::from_str(parts.uri.query().unwrap_or_default())
+                .map_err(ApiError::invalid)?;
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(ApiError::invalid)?;
+        if emulator.verify_signature {
+            emulator.verify(&queries, &parts.headers, &body)?;
+        }
+        let user_id = parts
+            .headers
+            .get(USER_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|user_id| !user_id.is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| ApiError::forbidden(format!("missing {} header", USER_ID_HEADER)))?;
+        Ok(Self {
+            emulator: emulator.clone(),
+            queries,
+            user_id,
+            body,
+        })
+    }
+}
+
+impl Call {
+    fn query<T>(&self, name: &str) -> Result<Option<T>, ApiError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.queries
+            .get(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| ApiError::invalid(format!("invalid {} {:?}: {}", name, value, e)))
+            })
+            .transpose()
+    }
+
+    fn required<T>(&self, name: &str) -> Result<T, ApiError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.query(name)?
+            .ok_or_else(|| ApiError::invalid(format!("missing {}", name)))
+    }
+
+    /// The JSON body, or the default when the body is empty.
+    fn json<T: DeserializeOwned + Default>(&self) -> Result<T, ApiError> {
+        if self.body.is_empty() {
+            return Ok(T::default());
+        }
+        serde_json::from_slice(&self.body).map_err(ApiError::invalid)
+    }
+
+    /// Maps `path` into the emulated directory, creating the caller's user
+    /// directory on first use.
+    async fn local_path(&self, path: Option<&RemotePath>) -> Result<PathBuf, ApiError> {
+        let path = path.ok_or_else(|| ApiError::invalid("missing Path"))?;
+        let mut segments = path
+            .as_str()
+            .split('/')
+            .filter(|segment| !segment.is_empty());
+        if segments.next() != Some(self.user_id.as_str()) {
+            return Err(ApiError::forbidden(format!(
+                "{} is outside the storage of user {}",
+                path, self.user_id
+            )));
+        }
+        let mut local_path = self.emulator.root.join(&self.user_id);
+        fs::create_dir_all(&local_path)
+            .await
+            .map_err(|e| ApiError::io(&local_path, e))?;
+        local_path.extend(segments);
+        Ok(local_path)
+    }
+
+    async fn query_path(&self) -> Result<(RemotePath, PathBuf), ApiError> {
+        let path: RemotePath = self.required("Path")?;
+        let local_path = self.local_path(Some(&path)).await?;
+        Ok((path, local_path))
+    }
+
+    fn ok<T>(&self, data: T) -> Json<BaseResponse<T>> {
+        Json(BaseResponse {
+            error_code: String::new(),
+            error_msg: String::new(),
+            request_id: next_request_id(),
+            data: Some(data),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl Display) -> Self {
+        Self {
+            status,
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid(message: impl Display) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+    }
+
+    fn unauthorized(message: impl Display) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "InvalidSignature", message)
+    }
+
+    fn forbidden(message: impl Display) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "PermissionDenied", message)
+    }
+
+    fn exists(path: &RemotePath) -> Self {
+        Self::new(
+            StatusCode::CONFLICT,
+            "PathExists",
+            format!("{} already exists", path),
+        )
+    }
+
+    fn io(path: impl AsRef<Path>, error: io::Error) -> Self {
+        let message = format!("{}: {}", path.as_ref().display(), error);
+        match error.kind() {
+            io::ErrorKind::NotFound => Self::new(StatusCode::NOT_FOUND, "PathNotFound", message),
+            io::ErrorKind::AlreadyExists => Self::new(StatusCode::CONFLICT, "PathExists", message),
+            _ => Self::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", message),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body: BaseResponse<()> = BaseResponse {
+            error_code: self.code.to_string(),
+            error_msg: self.message,
+            request_id: next_request_id(),
+            data: None,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<Json<BaseResponse<T>>, ApiError>;
+
+fn next_request_id() -> String {
+    format!("emulator-{}", REQUEST_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+fn file_info(name: &str, metadata: &Metadata) -> FileInfo {
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        (metadata.permissions().mode() & 0o777) as isize
+    };
+    #[cfg(not(unix))]
+    let mode = if metadata.is_dir() { 0o755 } else { 0o644 };
+    FileInfo {
+        name: name.to_string(),
+        size: if metadata.is_dir() {
+            0
+        } else {
+            metadata.len() as isize
+        },
+        mode,
+        mod_time: metadata
+            .modified()
+            .map(|modified| DateTime::<Utc>::from(modified).to_rfc3339())
+            .unwrap_or_default(),
+        is_dir: metadata.is_dir(),
+    }
+}
+
+async fn metadata(local_path: &Path) -> Result<Metadata, ApiError> {
+    fs::metadata(local_path)
+        .await
+        .map_err(|e| ApiError::io(local_path, e))
+}
+
+/// Refuses to move or remove `/` and user directories.
+fn ensure_below_home(path: &RemotePath) -> Result<(), ApiError> {
+    if path.parent().is_none_or(|parent| parent.as_str() == "/") {
+        return Err(ApiError::forbidden(format!("cannot modify {}", path)));
+    }
+    Ok(())
+}
+
+async fn list(call: Call) -> ApiResult<ApiStorageListResponse> {
+    let (_, local_path) = call.query_path().await?;
+    let page_offset: usize = call.query("PageOffset")?.unwrap_or(0);
+    let page_size: usize = call.query("PageSize")?.unwrap_or(DEFAULT_PAGE_SIZE);
+    let request: ApiStorageListRequest = call.json()?;
+    let filters = call
+        .queries
+        .get("FilterRegexp")
+        .into_iter()
+        .chain(request.filter_regexp_list.iter().flatten())
+        .map(|pattern| Regex::new(pattern).map_err(ApiError::invalid))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?
+    {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !filters.is_empty() && !filters.iter().any(|filter| filter.is_match(&name)) {
+            continue;
+        }
+        let metadata = metadata(&entry.path()).await?;
+        files.push(file_info(&name, &metadata));
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let total = files.len();
+    let files: Vec<FileInfo> = files
+        .into_iter()
+        .skip(page_offset)
+        .take(page_size)
+        .collect();
+    Ok(call.ok(ApiStorageListResponse {
+        next_marker: (page_offset + files.len()) as isize,
+        total: total as isize,
+        files,
+    }))
+}
+
+async fn stat(call: Call) -> ApiResult<ApiStorageStatResponse> {
+    let (path, local_path) = call.query_path().await?;
+    let metadata = metadata(&local_path).await?;
+    let name = path.file_name().unwrap_or("/");
+    Ok(call.ok(ApiStorageStatResponse {
+        file: Some(file_info(name, &metadata)),
+    }))
+}
+
+async fn mkdir(call: Call) -> ApiResult<ApiStorageMkDirResponse> {
+    let request: ApiStorageMkDirRequest = call.json()?;
+    let local_path = call.local_path(request.path.as_ref()).await?;
+    if let Ok(metadata) = fs::metadata(&local_path).await {
+        if metadata.is_dir() && request.ignore_exist.unwrap_or(false) {
+            return Ok(call.ok(ApiStorageMkDirResponse {}));
+        }
+        return Err(ApiError::exists(request.path.as_ref().unwrap()));
+    }
+    fs::create_dir(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    Ok(call.ok(ApiStorageMkDirResponse {}))
+}
+
+/// Refuses an existing destination: the API does not document that `mv`
+/// replaces one, so callers must not rely on it.
+async fn rename(call: Call) -> ApiResult<ApiStorageMoveResponse> {
+    let request: ApiStorageMoveRequest = call.json()?;
+    for path in [&request.src_path, &request.dest_path]
+        .into_iter()
+        .flatten()
+    {
+        ensure_below_home(path)?;
+    }
+    let src = call.local_path(request.src_path.as_ref()).await?;
+    let dest = call.local_path(request.dest_path.as_ref()).await?;
+    metadata(&src).await?;
+    if fs::try_exists(&dest).await.unwrap_or(false) {
+        return Err(ApiError::exists(request.dest_path.as_ref().unwrap()));
+    }
+    fs::rename(&src, &dest)
+        .await
+        .map_err(|e| ApiError::io(&dest, e))?;
+    Ok(call.ok(ApiStorageMoveResponse {}))
+}
+
+async fn remove(call: Call) -> ApiResult<ApiStorageRemoveResponse> {
+    let request: ApiStorageRemoveRequest = call.json()?;
+    if let Some(path) = &request.path {
+        ensure_below_home(path)?;
+    }
+    let local_path = call.local_path(request.path.as_ref()).await?;
+    let removed = match fs::metadata(&local_path).await {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&local_path).await,
+        Ok(_) => fs::remove_file(&local_path).await,
+        Err(e) => Err(e),
+    };
+    match removed {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && request.ignore_not_exist == Some(true) => {
+        }
+        removed => removed.map_err(|e| ApiError::io(&local_path, e))?,
+    }
+    Ok(call.ok(ApiStorageRemoveResponse {}))
+}
+
+/// Empties an existing file.
+async fn truncate(call: Call) -> ApiResult<ApiStorageTruncateResponse> {
+    let request: ApiStorageTruncateRequest = call.json()?;
+    let local_path = call.local_path(request.path.as_ref()).await?;
+    let file = OpenOptions::new()
+        .write(true)
+        .open(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.set_len(0)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    Ok(call.ok(ApiStorageTruncateResponse {}))
+}
+
+async fn upload(call: Call) -> ApiResult<ApiStorageUploadResponse> {
+    let (path, local_path) = call.query_path().await?;
+    let overwrite: bool = call.query("Overwrite")?.unwrap_or(false);
+    if let Ok(metadata) = fs::metadata(&local_path).await
+        && (metadata.is_dir() || !overwrite)
+    {
+        return Err(ApiError::exists(&path));
+    }
+    fs::write(&local_path, &call.body)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    Ok(call.ok(ApiStorageUploadResponse {}))
+}
+
+/// Streams a file, honoring a `Range` query in the HTTP header syntax.
+async fn download(call: Call) -> Result<Response, ApiError> {
+    let (path, local_path) = call.query_path().await?;
+    let size = metadata(&local_path).await?.len();
+    let range = match call.queries.get("Range") {
+        Some(range) => Some(
+            ByteRange::from_http_range(range, size)
+                .map_err(|e| ApiError::new(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange", e))?,
+        ),
+        None => None,
+    };
+    let ByteRange { offset, length } = range.unwrap_or(ByteRange::new(0, size));
+
+    let mut file = File::open(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    let body = Body::from_stream(ReaderStream::new(file.take(length)));
+
+    let name = path.file_name().unwrap_or_default();
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    let content_disposition = format!(
+        "attachment; filename*=UTF-8''{}",
+        utf8_percent_encode(name, NON_ALPHANUMERIC)
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&content_disposition).map_err(ApiError::invalid)?,
+    );
+    if range.is_some() {
+        let content_range = format!("bytes {}-{}/{}", offset, offset + length - 1, size);
+        headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).map_err(ApiError::invalid)?,
+        );
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    }
+    Ok(response)
+}
+
+/// Returns up to `Length` bytes from `Offset`, fewer at the end of the file.
+async fn read_at(call: Call) -> Result<Response, ApiError> {
+    let (_, local_path) = call.query_path().await?;
+    let offset: u64 = call.query("Offset")?.unwrap_or(0);
+    let length: u64 = call.required("Length")?;
+    let compressor: Compressor = call.query("Compressor")?.unwrap_or_default();
+
+    let mut file = File::open(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.take(length)
+        .read_to_end(&mut data)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    let data = compressor
+        .compress(&data)
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], data).into_response())
+}
+
+/// Writes the body at `Offset`, creating the file and zero-filling any gap.
+async fn write_at(call: Call) -> ApiResult<ApiStorageWriteAtResponse> {
+    let (path, local_path) = call.query_path().await?;
+    let offset: u64 = call.query("Offset")?.unwrap_or(0);
+    let length: Option<u64> = call.query("Length")?;
+    let compressor: Compressor = call.query("Compressor")?.unwrap_or_default();
+    let data = compressor
+        .decompress(&call.body)
+        .map_err(ApiError::invalid)?;
+    if length.is_some_and(|length| length != data.len() as u64) {
+        return Err(ApiError::invalid(format!(
+            "Length {} does not match the {} bytes received",
+            length.unwrap_or_default(),
+            data.len()
+        )));
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.write_all(&data)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.flush()
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+
+    let metadata = metadata(&local_path).await?;
+    Ok(call.ok(ApiStorageWriteAtResponse {
+        file: Some(file_info(path.file_name().unwrap_or_default(), &metadata)),
+    }))
+}
+
+/// Checksums every `BlockSize` block starting at byte `BeginChunkOffset` and
+/// ending before byte `EndChunkOffset`, or at the end of the file when it is
+/// missing or zero.
+async fn checksum(call: Call) -> ApiResult<ApiStorageChunkCheckSumsResponse> {
+    let (_, local_path) = call.query_path().await?;
+    let block_size: usize = call.required("BlockSize")?;
+    if block_size == 0 {
+        return Err(ApiError::invalid("BlockSize must be positive"));
+    }
+    let begin: u64 = call.query("BeginChunkOffset")?.unwrap_or(0);
+    let end: u64 = call.query("EndChunkOffset")?.unwrap_or(0);
+    let hash_type = RollingHashType::try_from(call.query::<isize>("RollingHashType")?.unwrap_or(0))
+        .map_err(ApiError::invalid)?;
+
+    let mut file = File::open(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    file.seek(SeekFrom::Start(begin))
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    let limit = if end > begin { end - begin } else { u64::MAX };
+    let mut checksums = chunk_checksums(file.take(limit), block_size, hash_type)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    for checksum in &mut checksums {
+        checksum.chunk_offset += begin as isize;
+    }
+    Ok(call.ok(ApiStorageChunkCheckSumsResponse {
+        checksums: Some(checksums),
+    }))
+}
+
+/// Slides a window over the file from byte `BeginChunkOffset` to before byte
+/// `EndChunkOffset` (or the end of the file) and returns the first offset at
+/// which each of `Checksums` matches by weak and strong checksum.
+async fn find_chunks(call: Call) -> ApiResult<ApiStorageFindChunksResponse> {
+    let request: ApiStorageCheckSumsFindChunkRequest = call.json()?;
+    let local_path = call.local_path(request.path.as_ref()).await?;
+    let hash_type = RollingHashType::try_from(request.rolling_hash_type.unwrap_or(0))
+        .map_err(ApiError::invalid)?;
+    let data = fs::read(&local_path)
+        .await
+        .map_err(|e| ApiError::io(&local_path, e))?;
+    let begin = (request.begin_chunk_offset.unwrap_or(0).max(0) as usize).min(data.len());
+    let end = match request.end_chunk_offset.unwrap_or(0) {
+        end if end as usize > begin => (end as usize).min(data.len()),
+        _ => data.len(),
+    };
+    let data = &data[begin..end];
+
+    let checksums = request.chunks.unwrap_or_default();
+    let mut sizes: Vec<usize> = checksums
+        .iter()
+        .filter(|checksum| checksum.size > 0)
+        .map(|checksum| checksum.size as usize)
+        .collect();
+    sizes.sort_unstable();
+    sizes.dedup();
+    let mut found: Vec<Option<usize>> = vec![None; checksums.len()];
+    for size in sizes.into_iter().filter(|&size| size <= data.len()) {
+        let mut rolling = RollingChecksum::new(hash_type);
+        rolling.update(&data[..size]);
+        for offset in 0..=data.len() - size {
+            if offset > 0 {
+                rolling.roll(data[offset - 1], data[offset + size - 1]);
+            }
+            let weak = rolling.digest_bytes();
+            let mut strong = None;
+            for (index, checksum) in checksums.iter().enumerate() {
+                if found[index].is_some()
+                    || checksum.size as usize != size
+                    || checksum.weak_checksum != weak
+                {
+                    continue;
+                }
+                let strong =
+                    strong.get_or_insert_with(|| strong_checksum(&data[offset..offset + size]));
+                if checksum.strong_checksum == *strong {
+                    found[index] = Some(offset);
+                }
+            }
+        }
+    }
+
+    let chunks = checksums
+        .into_iter()
+        .zip(found)
+        .filter_map(|(checksum, offset)| {
+            Some(Chunk {
+                offset: (begin + offset?) as isize,
+                length: checksum.size,
+                weak_checksum: checksum.weak_checksum,
+                strong_checksum: checksum.strong_checksum,
+                ..Default::default()
+            })
+        })
+        .collect();
+    Ok(call.ok(ApiStorageFindChunksResponse {
+        chunks: Some(chunks),
+    }))
+}
+
+/// An emulator shared by the tests of modules that talk to storage.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use crate::common::client::OpenApiClient;
+    use crate::common::config::{EndpointType, OpenApiConfig};
+
+    static NEXT_ROOT: AtomicU64 = AtomicU64::new(0);
+
+    /// An emulator on a free local port serving a temp directory that is
+    /// removed on drop, and a client signed as user `u1`.
+    pub(crate) struct TestStorage {
+        pub config: OpenApiConfig,
+        pub client: OpenApiClient,
+        pub root: PathBuf,
+    }
+
+    impl TestStorage {
+        pub async fn start() -> anyhow::Result<Self> {
+            Self::start_with(|router| router).await
+        }
+
+        /// Serves the emulator router wrapped by `layer`, e.g. to inject faults.
+        pub async fn start_with(layer: impl FnOnce(Router) -> Router) -> anyhow::Result<Self> {
+            if std::env::var("XYsVersion").is_err() {
+                // SAFETY: the client reads the version from the environment and
+                // no test changes it concurrently.
+                unsafe { std::env::set_var("XYsVersion", "2022-05-01") };
+            }
+            let root = std::env::temp_dir().join(format!(
+                "openapi-rs-emulator-{}-{}",
+                std::process::id(),
+                NEXT_ROOT.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(&root).await?;
+            let listener = TcpListener::bind("127.0.0.1:0").await?;
+            let endpoint = format!("http://{}", listener.local_addr()?);
+            let router = layer(
+                StorageEmulator::new(&root)
+                    .with_credential("key", "secret")
+                    .router(),
+            );
+            tokio::spawn(async move { axum::serve(listener, router).await });
+
+            let config = OpenApiConfig::new()
+                .with_app_key("key".to_string())
+                .with_app_secret("secret".to_string())
+                .with_cloud_endpoint(endpoint)
+                .with_user_id("u1".to_string());
+            let client = Self::client(config.clone());
+            Ok(Self {
+                config,
+                client,
+                root,
+            })
+        }
+
+        pub fn client(config: OpenApiConfig) -> OpenApiClient {
+            OpenApiClient::new(config).with_endpoint_type(EndpointType::Cloud)
+        }
+    }
+
+    impl Drop for TestStorage {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::v1::storage::api_storage_read_at::ApiStorageReadAtRequest;
+    use crate::common::crypt::rolling::strong_checksum;
+    use crate::common::define::HttpBuilder;
+    use crate::storage::fs as remote;
+
+    #[tokio::test]
+    async fn test_storage_emulator() -> anyhow::Result<()> {
+        let storage = testing::TestStorage::start().await?;
+        let client = &storage.client;
+        let intruder = testing::TestStorage::client(
+            storage.config.clone().with_app_secret("guess".to_string()),
+        );
+        let anonymous =
+            testing::TestStorage::client(storage.config.clone().with_user_id(String::new()));
+
+        remote::mkdir(client, "/u1/data").await?;
+        remote::upload(client, "/u1/data/a.txt", b"hello world".to_vec(), false).await?;
+        assert!(
+            remote::upload(client, "/u1/data/a.txt", Vec::new(), false)
+                .await
+                .is_err()
+        );
+        remote::write_at(client, "/u1/data/a.txt", 6, Bytes::from_static(b"there")).await?;
+        assert_eq!(
+            remote::read_at(client, "/u1/data/a.txt", 6, 5).await?,
+            "there"
+        );
+        assert_eq!(
+            remote::download(client, "/u1/data/a.txt").await?,
+            "hello there"
+        );
+
+        let http_fn = ApiStorageReadAtRequest::new()
+            .with_path(RemotePath::new("/u1/data/a.txt")?)
+            .with_compressor(Compressor::Zstd)
+            .with_offset(0)
+            .with_length(5)
+            .builder();
+        let response = client.clone().send(http_fn).await?;
+        assert_eq!(response.data.unwrap_or_default(), "hello");
+
+        let file_info = remote::stat(client, "/u1/data/a.txt").await?;
+        assert_eq!((file_info.name.as_str(), file_info.size), ("a.txt", 11));
+        let names: Vec<String> = remote::list_dir(client, "/u1")
+            .await?
+            .into_iter()
+            .map(|file_info| file_info.name)
+            .collect();
+        assert_eq!(names, ["data"]);
+
+        let checksums = remote::chunk_checksums(client, "/u1/data/a.txt", 4, None).await?;
+        let offsets: Vec<(isize, isize)> = checksums
+            .iter()
+            .map(|checksum| (checksum.chunk_offset, checksum.size))
+            .collect();
+        assert_eq!(offsets, [(0, 4), (4, 4), (8, 3)]);
+        assert_eq!(checksums[2].strong_checksum, strong_checksum(b"ere"));
+
+        let wanted = chunk_checksums(&b"lo therezzzz"[..], 4, RollingHashType::Rsync).await?;
+        let chunks = remote::find_chunks(client, "/u1/data/a.txt", 4, None, wanted).await?;
+        let found: Vec<(isize, isize)> = chunks
+            .iter()
+            .map(|chunk| (chunk.offset, chunk.length))
+            .collect();
+        assert_eq!(found, [(3, 4), (7, 4)]);
+        assert_eq!(chunks[1].strong_checksum, strong_checksum(b"here"));
+
+        remote::rename(client, "/u1/data/a.txt", "/u1/data/b.txt").await?;
+        assert!(
+            remote::stat_if_exists(client, "/u1/data/a.txt")
+                .await?
+                .is_none()
+        );
+        remote::truncate(client, "/u1/data/b.txt").await?;
+        assert_eq!(remote::stat(client, "/u1/data/b.txt").await?.size, 0);
+        remote::remove(client, "/u1/data").await?;
+        assert!(remote::stat_if_exists(client, "/u1/data").await?.is_none());
+
+        assert!(remote::stat(&intruder, "/u1").await.is_err());
+        assert!(remote::mkdir(client, "/u2/data").await.is_err());
+        assert!(remote::stat(&anonymous, "/u1").await.is_err());
+        assert!(remote::mkdir(&anonymous, "/u2").await.is_err());
+        Ok(())
+    }
+}
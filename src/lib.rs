@@ -1,4 +1,9 @@
 #[allow(dead_code)]
 pub mod api;
 pub mod common;
+#[cfg(feature = "emulator")]
+pub mod emulator;
 pub mod model;
+pub mod storage;
+#[cfg(feature = "webdav")]
+pub mod webdav;
@@ -1,6 +1,7 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct FileInfo {
     #[serde(rename = "Name")]
@@ -15,6 +16,14 @@ pub struct FileInfo {
     pub is_dir: bool,
 }
 
+impl FileInfo {
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.mod_time)
+            .ok()
+            .map(|mod_time| mod_time.with_timezone(&Utc))
+    }
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ChunkChecksum {
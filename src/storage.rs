@@ -0,0 +1,20 @@
+pub mod archive;
+pub mod batch;
+pub mod block_cache;
+pub mod copy;
+pub mod delta;
+pub mod diff;
+pub mod du;
+pub mod fs;
+pub mod local_watcher;
+pub mod manager;
+pub mod mirror;
+pub mod parallel_download;
+pub mod progress;
+pub mod range;
+pub mod remote_file;
+pub mod remote_watcher;
+pub mod transfer;
+pub mod trash;
+pub mod verify;
+pub mod walk;
@@ -0,0 +1,354 @@
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpStreamBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::model::file::FileInfo;
+use crate::storage::mirror::{PathFilter, walk_local};
+use crate::storage::walk::walk;
+use crate::storage::{fs, transfer};
+use anyhow::{anyhow, bail};
+use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+use futures::{StreamExt, future, stream};
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_tar::{Builder, EntryType, Header};
+use tokio_util::io::StreamReader;
+use tracing::warn;
+
+/// Buffer between the archive writer and the upload.
+const PIPE_SIZE: usize = 1024 * 1024;
+const DEFAULT_DIR_MODE: u32 = 0o755;
+const DEFAULT_FILE_MODE: u32 = 0o644;
+
+/// Tar archives, optionally compressed. Zip is not supported: its central
+/// directory and per-entry sizes and CRCs do not fit the single streaming
+/// pass both directions are built on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[default]
+    Tar,
+    TarGz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Picks the format from an extension: `.tar`, `.tar.gz`/`.tgz` or
+    /// `.tar.zst`/`.tzst`.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_ascii_lowercase();
+        if path.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if path.ends_with(".tar.zst") || path.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else {
+            None
+        }
+    }
+
+    fn encoder<'a>(
+        self,
+        writer: impl AsyncWrite + Unpin + Send + 'a,
+    ) -> Box<dyn AsyncWrite + Unpin + Send + 'a> {
+        match self {
+            ArchiveFormat::Tar => Box::new(writer),
+            ArchiveFormat::TarGz => Box::new(GzipEncoder::new(writer)),
+            ArchiveFormat::TarZst => Box::new(ZstdEncoder::new(writer)),
+        }
+    }
+}
+
+/// Archives a local directory into the remote file `remote_path`, replacing
+/// it, and returns the archive size.
+///
+/// The archive is produced while it is uploaded through a bounded pipe, so
+/// neither a local temp file nor the whole archive in memory is needed. It is
+/// uploaded to a hidden temp sibling that is moved onto `remote_path` at the
+/// end, so a failed upload leaves `remote_path` as it was. Entries are
+/// relative to `local_dir`.
+pub async fn upload_archive(
+    client: &OpenApiClient,
+    local_dir: impl AsRef<Path>,
+    remote_path: &str,
+    format: ArchiveFormat,
+) -> anyhow::Result<u64> {
+    let local_dir = local_dir.as_ref();
+    let temp_path = transfer::temp_sibling(remote_path)?;
+    let result = async {
+        let (writer, reader) = tokio::io::duplex(PIPE_SIZE);
+        let write = write_local_archive(local_dir, format.encoder(writer));
+        let upload = transfer::upload_reader(client, reader, &temp_path);
+        let (_, size) = tokio::try_join!(write, upload)?;
+        transfer::replace_file(client, &temp_path, remote_path).await?;
+        Ok(size)
+    }
+    .await;
+
+    if result.is_err()
+        && let Err(e) = fs::remove(client, &temp_path).await
+    {
+        warn!("failed to remove temp file {}: {:#}", temp_path, e);
+    }
+    result
+}
+
+/// Walks the remote directory `remote_dir` and writes its tree as an archive
+/// to `writer`, downloading every file while it is written. Entries are
+/// relative to `remote_dir`; `writer` is shut down at the end.
+pub async fn download_as_archive(
+    client: &OpenApiClient,
+    remote_dir: &str,
+    writer: impl AsyncWrite + Unpin + Send,
+    format: ArchiveFormat,
+) -> anyhow::Result<()> {
+    let root = RemotePath::new(remote_dir)?.to_string();
+    let mut builder = Builder::new_non_terminated(format.encoder(writer));
+    let mut entries = walk(client, &root).into_stream();
+    while let Some(entry) = entries.next().await {
+        let (path, file_info) = entry?;
+        let Some(name) = path
+            .strip_prefix(root.trim_end_matches('/'))
+            .and_then(|name| name.strip_prefix('/'))
+        else {
+            bail!("{} is not below {}", path, root);
+        };
+        let mut header = remote_header(&file_info);
+        if file_info.is_dir {
+            builder
+                .append_data(&mut header, name, tokio::io::empty())
+                .await?;
+        } else {
+            let data = download_reader(client, &path, header.size()?).await?;
+            builder.append_data(&mut header, name, data).await?;
+        }
+    }
+    let mut writer = builder.into_inner().await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+async fn write_local_archive(
+    local_dir: &Path,
+    writer: impl AsyncWrite + Unpin + Send,
+) -> anyhow::Result<()> {
+    let filter = PathFilter::new(&[], &[])?;
+    let (dirs, files) = walk_local(local_dir, &filter).await?;
+    let mut entries: Vec<(String, bool)> = dirs
+        .into_iter()
+        .map(|dir| (dir, true))
+        .chain(files.into_iter().map(|(path, _)| (path, false)))
+        .collect();
+    // a directory sorts before its content
+    entries.sort();
+
+    let mut builder = Builder::new_non_terminated(writer);
+    for (path, is_dir) in entries {
+        let local_path = local_dir.join(&path);
+        if is_dir {
+            builder.append_dir(&path, &local_path).await?;
+        } else {
+            builder.append_path_with_name(&local_path, &path).await?;
+        }
+    }
+    let mut writer = builder.into_inner().await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+fn remote_header(file_info: &FileInfo) -> Header {
+    let mut header = Header::new_gnu();
+    let mode = file_info.mode as u32 & 0o777;
+    if file_info.is_dir {
+        header.set_entry_type(EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(if mode == 0 { DEFAULT_DIR_MODE } else { mode });
+    } else {
+        header.set_entry_type(EntryType::Regular);
+        header.set_size(file_info.size as u64);
+        header.set_mode(if mode == 0 { DEFAULT_FILE_MODE } else { mode });
+    }
+    let mtime = file_info
+        .modified()
+        .map_or(0, |modified| modified.timestamp());
+    header.set_mtime(mtime.max(0) as u64);
+    header
+}
+
+/// Streams a remote file, failing unless exactly `size` bytes arrive so that
+/// a file changed during the walk cannot corrupt the archive.
+async fn download_reader(
+    client: &OpenApiClient,
+    path: &str,
+    size: u64,
+) -> anyhow::Result<impl AsyncRead + Unpin + Send> {
+    let http_fn = ApiStorageDownloadRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .stream_builder();
+    let response = client.clone().send(http_fn).await?;
+    let data = client.throttle(response.stream.ok_or_else(|| anyhow!("stream not found"))?);
+
+    let path = path.to_string();
+    let checked = data
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .scan(0u64, move |received, chunk| {
+            let item = match chunk {
+                Some(Ok(chunk)) => {
+                    *received += chunk.len() as u64;
+                    Some(Ok(chunk))
+                }
+                Some(Err(e)) => Some(Err(e)),
+                None if *received != size => Some(Err(io::Error::other(format!(
+                    "{} changed while archiving: expected {} bytes but received {}",
+                    path, size, received
+                )))),
+                None => None,
+            };
+            future::ready(item)
+        });
+    Ok(StreamReader::new(Box::pin(checked)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::bufread::GzipDecoder;
+    use tokio::io::AsyncReadExt;
+    use tokio_tar::Archive;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path("/u1/in.tar"),
+            Some(ArchiveFormat::Tar)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path("in.TGZ"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path("in.tar.zst"),
+            Some(ArchiveFormat::TarZst)
+        );
+        assert_eq!(ArchiveFormat::from_path("in.zip"), None);
+    }
+
+    #[tokio::test]
+    async fn test_write_local_archive() -> anyhow::Result<()> {
+        let local_dir =
+            std::env::temp_dir().join(format!("openapi-rs-archive-{}", std::process::id()));
+        tokio::fs::create_dir_all(local_dir.join("case/mesh")).await?;
+        tokio::fs::write(local_dir.join("case/run.sh"), "#!/bin/sh\n").await?;
+        tokio::fs::write(local_dir.join("case/mesh/a.inp"), vec![7u8; 1000]).await?;
+
+        let mut compressed = Vec::new();
+        let written =
+            write_local_archive(&local_dir, ArchiveFormat::TarGz.encoder(&mut compressed)).await;
+        tokio::fs::remove_dir_all(&local_dir).await?;
+        written?;
+
+        let entries = read_archive(&compressed).await?;
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["case", "case/mesh", "case/mesh/a.inp", "case/run.sh"]
+        );
+        assert_eq!(entries[2].1, vec![7u8; 1000]);
+        Ok(())
+    }
+
+    async fn read_archive(compressed: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        let mut archive = Archive::new(GzipDecoder::new(compressed));
+        let mut entries = archive.entries()?;
+        let mut read = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry?;
+            let name = entry
+                .path()?
+                .to_string_lossy()
+                .trim_end_matches('/')
+                .to_string();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).await?;
+            read.push((name, content));
+        }
+        read.sort();
+        Ok(read)
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_archive_round_trip() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let fail_move = Arc::new(AtomicBool::new(false));
+        let fail_move_ref = fail_move.clone();
+        let storage = TestStorage::start_with(move |router| {
+            router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let fail_move = fail_move_ref.clone();
+                async move {
+                    if request.uri().path() == "/api/storage/mv"
+                        && fail_move.load(Ordering::Relaxed)
+                    {
+                        return (StatusCode::SERVICE_UNAVAILABLE, "unavailable").into_response();
+                    }
+                    next.run(request).await
+                }
+            }))
+        })
+        .await?;
+        let client = &storage.client;
+        let local_dir = storage.root.join("local");
+        tokio::fs::create_dir_all(local_dir.join("case/mesh")).await?;
+        tokio::fs::write(local_dir.join("case/run.sh"), "#!/bin/sh\n").await?;
+        tokio::fs::write(local_dir.join("case/mesh/a.inp"), vec![7u8; 1000]).await?;
+        fs::mkdir_all(client, "/u1/tree/case/mesh").await?;
+        fs::upload(
+            client,
+            "/u1/tree/case/run.sh",
+            b"#!/bin/sh\n".to_vec(),
+            true,
+        )
+        .await?;
+        fs::upload(client, "/u1/tree/case/mesh/a.inp", vec![7u8; 1000], true).await?;
+
+        let size = upload_archive(client, &local_dir, "/u1/case.tgz", ArchiveFormat::TarGz).await?;
+        let uploaded = fs::download(client, "/u1/case.tgz").await?;
+        assert_eq!(uploaded.len() as u64, size);
+        let mut downloaded = Vec::new();
+        download_as_archive(client, "/u1/tree", &mut downloaded, ArchiveFormat::TarGz).await?;
+
+        let entries = read_archive(&downloaded).await?;
+        assert_eq!(read_archive(&uploaded).await?, entries);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["case", "case/mesh", "case/mesh/a.inp", "case/run.sh"]
+        );
+
+        // a failed upload keeps the previous archive and removes its temp file
+        fail_move.store(true, Ordering::Relaxed);
+        tokio::fs::write(local_dir.join("case/run.sh"), "changed").await?;
+        assert!(
+            upload_archive(client, &local_dir, "/u1/case.tgz", ArchiveFormat::TarGz)
+                .await
+                .is_err()
+        );
+        assert_eq!(fs::download(client, "/u1/case.tgz").await?, uploaded);
+        let names: Vec<String> = fs::list_dir(client, "/u1")
+            .await?
+            .into_iter()
+            .map(|file_info| file_info.name)
+            .collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+        Ok(())
+    }
+}
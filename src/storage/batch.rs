@@ -0,0 +1,244 @@
+use crate::common::client::OpenApiClient;
+use crate::common::remote_path::RemotePath;
+use crate::model::file::FileInfo;
+use crate::storage::fs;
+use anyhow::anyhow;
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
+
+const DEFAULT_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BatchFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Outcome of every item of a batch, each list in input order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchReport<T> {
+    pub succeeded: Vec<(String, T)>,
+    pub failed: Vec<BatchFailure>,
+}
+
+impl<T> Default for BatchReport<T> {
+    fn default() -> Self {
+        Self {
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+}
+
+impl<T> BatchReport<T> {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    fn record(&mut self, path: String, outcome: anyhow::Result<T>) {
+        match outcome {
+            Ok(value) => self.succeeded.push((path, value)),
+            Err(e) => self.failed.push(BatchFailure {
+                path,
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+}
+
+/// Runs many storage operations with bounded concurrency. A failed item is
+/// recorded in the report and does not stop the others.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    client: OpenApiClient,
+    concurrency: usize,
+}
+
+impl Batch {
+    pub fn new(client: OpenApiClient) -> Self {
+        Self {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Removes files and directories; paths that do not exist succeed.
+    pub async fn remove<P: AsRef<str>>(
+        &self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> BatchReport<()> {
+        let client = &self.client;
+        let items = paths.into_iter().map(|path| {
+            let path = path.as_ref().to_string();
+            (path.clone(), async move { fs::remove(client, &path).await })
+        });
+        self.run(items).await
+    }
+
+    pub async fn stat<P: AsRef<str>>(
+        &self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> BatchReport<FileInfo> {
+        let client = &self.client;
+        let items = paths.into_iter().map(|path| {
+            let path = path.as_ref().to_string();
+            (path.clone(), async move { fs::stat(client, &path).await })
+        });
+        self.run(items).await
+    }
+
+    /// Moves every `(src, dest)` pair; the report is keyed by `src`.
+    pub async fn rename<S: AsRef<str>, D: AsRef<str>>(
+        &self,
+        pairs: impl IntoIterator<Item = (S, D)>,
+    ) -> BatchReport<()> {
+        let client = &self.client;
+        let items = pairs.into_iter().map(|(src, dest)| {
+            let src = src.as_ref().to_string();
+            let dest = dest.as_ref().to_string();
+            (
+                src.clone(),
+                async move { fs::rename(client, &src, &dest).await },
+            )
+        });
+        self.run(items).await
+    }
+
+    /// Creates every directory and its missing ancestors, like `mkdir -p`.
+    ///
+    /// Directories shared by several paths are created once, one depth level
+    /// at a time; a path fails when it or one of its ancestors could not be
+    /// created.
+    pub async fn mkdir_all<P: AsRef<str>>(
+        &self,
+        paths: impl IntoIterator<Item = P>,
+    ) -> BatchReport<()> {
+        let paths: Vec<(String, anyhow::Result<RemotePath>)> = paths
+            .into_iter()
+            .map(|path| (path.as_ref().to_string(), RemotePath::new(path.as_ref())))
+            .collect();
+        let levels = dirs_to_create(paths.iter().filter_map(|(_, path)| path.as_ref().ok()));
+
+        let mut errors: HashMap<String, String> = HashMap::new();
+        for dirs in levels.into_values() {
+            let client = &self.client;
+            let errors_ref = &errors;
+            let outcomes: Vec<(String, anyhow::Result<()>)> = stream::iter(dirs)
+                .map(|dir| async move {
+                    let parent_error = RemotePath::new(&dir)
+                        .ok()
+                        .and_then(|dir| dir.parent())
+                        .and_then(|parent| errors_ref.get(parent.as_str()).cloned());
+                    let outcome = match parent_error {
+                        Some(error) => Err(anyhow!(error)),
+                        None => fs::mkdir(client, &dir).await,
+                    };
+                    (dir, outcome)
+                })
+                .buffer_unordered(self.concurrency)
+                .collect()
+                .await;
+            for (dir, outcome) in outcomes {
+                if let Err(e) = outcome {
+                    errors.insert(dir, format!("{:#}", e));
+                }
+            }
+        }
+
+        let mut report = BatchReport::default();
+        for (input, path) in paths {
+            let outcome = path.and_then(|path| {
+                let mut current = Some(path);
+                while let Some(dir) = current {
+                    if let Some(error) = errors.get(dir.as_str()) {
+                        return Err(anyhow!("{}: {}", dir, error));
+                    }
+                    current = dir.parent();
+                }
+                Ok(())
+            });
+            report.record(input, outcome);
+        }
+        report
+    }
+
+    async fn run<T, F>(&self, items: impl Iterator<Item = (String, F)>) -> BatchReport<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let outcomes: Vec<(String, anyhow::Result<T>)> = stream::iter(items)
+            .map(|(path, operation)| async move { (path, operation.await) })
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+        let mut report = BatchReport::default();
+        for (path, outcome) in outcomes {
+            report.record(path, outcome);
+        }
+        report
+    }
+}
+
+/// Every directory below the user root needed by `paths`, grouped by depth.
+fn dirs_to_create<'a>(
+    paths: impl Iterator<Item = &'a RemotePath>,
+) -> BTreeMap<usize, BTreeSet<String>> {
+    let mut levels: BTreeMap<usize, BTreeSet<String>> = BTreeMap::new();
+    for path in paths {
+        let mut current = Some(path.clone());
+        while let Some(dir) = current {
+            current = dir.parent();
+            let depth = dir.as_str().matches('/').count();
+            // the user root always exists
+            if depth > 1 {
+                levels.entry(depth).or_default().insert(dir.to_string());
+            }
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirs_to_create() {
+        let paths = [
+            RemotePath::new("/u1/a/b/c").unwrap(),
+            RemotePath::new("/u1/a/d").unwrap(),
+            RemotePath::new("/u1").unwrap(),
+            RemotePath::new("/u1/e").unwrap(),
+        ];
+        let levels: Vec<Vec<String>> = dirs_to_create(paths.iter())
+            .into_values()
+            .map(|dirs| dirs.into_iter().collect())
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                vec!["/u1/a", "/u1/e"],
+                vec!["/u1/a/b", "/u1/a/d"],
+                vec!["/u1/a/b/c"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_record() {
+        let mut report = BatchReport::default();
+        report.record("/u1/a".to_string(), Ok(()));
+        report.record("/u1/b".to_string(), Err(anyhow!("denied")));
+        assert!(!report.is_success());
+        assert_eq!(report.succeeded, [("/u1/a".to_string(), ())]);
+        assert_eq!(report.failed[0].path, "/u1/b");
+        assert_eq!(report.failed[0].error, "denied");
+    }
+}
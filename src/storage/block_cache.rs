@@ -0,0 +1,310 @@
+use crate::common::client::{OpenApiClient, RequestObserver};
+use crate::common::define::BaseRequest;
+use crate::model::file::FileInfo;
+use crate::storage::fs;
+use bytes::{Bytes, BytesMut};
+use lru::LruCache;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+const DEFAULT_BLOCK_SIZE: u64 = 1024 * 1024;
+const DEFAULT_READ_AHEAD: usize = 4;
+const MAX_TRACKED_FILES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BlockKey {
+    path: String,
+    index: u64,
+    mod_time: String,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    blocks: LruCache<BlockKey, Bytes>,
+    last_block: LruCache<String, u64>,
+    in_flight: HashSet<BlockKey>,
+    generation: u64,
+}
+
+/// An LRU cache of fixed-size `readAt` blocks keyed by path, block index and
+/// `FileInfo::mod_time`.
+///
+/// Reading the block after the last one read from a file is treated as
+/// sequential access and fetches the next `read_ahead` blocks in the
+/// background. Register the cache with
+/// [`OpenApiClient::with_request_observer`] so that `writeAt`, `upload`,
+/// `truncate`, `mv` and `rm` sent through that client invalidate the paths
+/// they touch.
+#[derive(Debug, Clone)]
+pub struct BlockCache {
+    state: Arc<Mutex<CacheState>>,
+    block_size: u64,
+    read_ahead: usize,
+}
+
+impl BlockCache {
+    /// Creates a cache holding at most `capacity` blocks.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(CacheState {
+                blocks: LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap()),
+                last_block: LruCache::new(NonZeroUsize::new(MAX_TRACKED_FILES).unwrap()),
+                in_flight: HashSet::new(),
+                generation: 0,
+            })),
+            block_size: DEFAULT_BLOCK_SIZE,
+            read_ahead: DEFAULT_READ_AHEAD,
+        }
+    }
+
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Number of blocks fetched ahead once sequential access is detected, 0 to disable.
+    pub fn with_read_ahead(mut self, read_ahead: usize) -> Self {
+        self.read_ahead = read_ahead;
+        self
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Number of blocks currently cached.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads `length` bytes at `offset` of the file described by `file_info`,
+    /// clamped to the end of the file.
+    pub async fn read_at(
+        &self,
+        client: &OpenApiClient,
+        path: &str,
+        file_info: &FileInfo,
+        offset: u64,
+        length: u64,
+    ) -> anyhow::Result<Bytes> {
+        let size = file_info.size as u64;
+        let end = offset.saturating_add(length).min(size);
+        if offset >= end {
+            return Ok(Bytes::new());
+        }
+        let first = offset / self.block_size;
+        let last = (end - 1) / self.block_size;
+        if first == last {
+            let block = self.read_block(client, path, file_info, first).await?;
+            let start = (offset - first * self.block_size) as usize;
+            return Ok(block.slice(start..start + (end - offset) as usize));
+        }
+
+        let mut data = BytesMut::with_capacity((end - offset) as usize);
+        for index in first..=last {
+            let block = self.read_block(client, path, file_info, index).await?;
+            let block_start = index * self.block_size;
+            let start = offset.saturating_sub(block_start) as usize;
+            let stop = (end - block_start).min(block.len() as u64) as usize;
+            data.extend_from_slice(&block[start..stop]);
+        }
+        Ok(data.freeze())
+    }
+
+    /// Returns block `index` of the file, fetching it with `readAt` on a miss.
+    /// The last block of a file may be shorter than the block size.
+    pub async fn read_block(
+        &self,
+        client: &OpenApiClient,
+        path: &str,
+        file_info: &FileInfo,
+        index: u64,
+    ) -> anyhow::Result<Bytes> {
+        let key = BlockKey {
+            path: path.to_string(),
+            index,
+            mod_time: file_info.mod_time.clone(),
+        };
+        let size = file_info.size as u64;
+        let (cached, read_ahead) = {
+            let mut state = self.state.lock().unwrap();
+            let sequential = index > 0 && state.last_block.get(path) == Some(&(index - 1));
+            state.last_block.put(path.to_string(), index);
+            let read_ahead: Vec<BlockKey> = if sequential {
+                (index + 1..=index + self.read_ahead as u64)
+                    .filter(|next| next * self.block_size < size)
+                    .map(|next| BlockKey {
+                        index: next,
+                        ..key.clone()
+                    })
+                    .filter(|next| !state.blocks.contains(next) && !state.in_flight.contains(next))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            for next in &read_ahead {
+                state.in_flight.insert(next.clone());
+            }
+            (state.blocks.get(&key).cloned(), read_ahead)
+        };
+
+        for next in read_ahead {
+            let cache = self.clone();
+            let client = client.clone();
+            tokio::spawn(async move {
+                if let Err(e) = cache.fetch(&client, &next, size).await {
+                    debug!(
+                        "read ahead {} block {} failed: {}",
+                        next.path, next.index, e
+                    );
+                }
+                cache.state.lock().unwrap().in_flight.remove(&next);
+            });
+        }
+
+        match cached {
+            Some(block) => Ok(block),
+            None => self.fetch(client, &key, size).await,
+        }
+    }
+
+    /// Drops every cached block of `path` and of any path below it.
+    ///
+    /// Fetches that started before the invalidation are not cached when they
+    /// complete, so a read racing with a write cannot reinsert stale data.
+    pub fn invalidate(&self, path: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        let stale: Vec<BlockKey> = state
+            .blocks
+            .iter()
+            .filter(|(key, _)| fs::is_within(&key.path, path))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            state.blocks.pop(&key);
+        }
+        state.last_block.pop(path);
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.blocks.clear();
+        state.last_block.clear();
+    }
+
+    async fn fetch(
+        &self,
+        client: &OpenApiClient,
+        key: &BlockKey,
+        size: u64,
+    ) -> anyhow::Result<Bytes> {
+        let generation = self.state.lock().unwrap().generation;
+        let offset = key.index * self.block_size;
+        let length = self.block_size.min(size.saturating_sub(offset));
+        let data = fs::read_at(client, &key.path, offset, length).await?;
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            state.blocks.put(key.clone(), data.clone());
+        }
+        Ok(data)
+    }
+}
+
+impl RequestObserver for BlockCache {
+    fn on_request_sent(&self, request: &BaseRequest) {
+        for path in modified_paths(request) {
+            self.invalidate(&path);
+        }
+    }
+}
+
+/// Paths whose content a storage request may have changed.
+fn modified_paths(request: &BaseRequest) -> Vec<String> {
+    match request.uri.as_str() {
+        "/api/storage/writeAt" | "/api/storage/upload/file" => request
+            .queries
+            .as_ref()
+            .and_then(|queries| queries.get("Path").cloned())
+            .into_iter()
+            .collect(),
+        "/api/storage/truncate" | "/api/storage/rm" | "/api/storage/mv" => {
+            let Ok(body) = serde_json::from_slice::<serde_json::Value>(&request.body) else {
+                return Vec::new();
+            };
+            ["Path", "Src", "Dest"]
+                .into_iter()
+                .filter_map(|field| body.get(field)?.as_str().map(str::to_string))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+    use std::collections::HashMap;
+
+    fn insert(cache: &BlockCache, path: &str, index: u64) {
+        let key = BlockKey {
+            path: path.to_string(),
+            index,
+            mod_time: "2024-01-01T00:00:00Z".to_string(),
+        };
+        cache
+            .state
+            .lock()
+            .unwrap()
+            .blocks
+            .put(key, Bytes::from_static(b"block"));
+    }
+
+    #[test]
+    fn test_invalidate_on_request() {
+        let cache = BlockCache::new(16);
+        insert(&cache, "/u/a.txt", 0);
+        insert(&cache, "/u/a.txt", 1);
+        insert(&cache, "/u/ab.txt", 0);
+        insert(&cache, "/u/dir/b.txt", 0);
+        assert_eq!(cache.len(), 4);
+
+        cache.on_request_sent(&BaseRequest {
+            method: Method::POST,
+            uri: "/api/storage/writeAt".to_string(),
+            queries: Some(HashMap::from([(
+                "Path".to_string(),
+                "/u/a.txt".to_string(),
+            )])),
+            ..Default::default()
+        });
+        assert_eq!(cache.len(), 2);
+
+        cache.on_request_sent(&BaseRequest {
+            method: Method::POST,
+            uri: "/api/storage/stat".to_string(),
+            queries: Some(HashMap::from([(
+                "Path".to_string(),
+                "/u/ab.txt".to_string(),
+            )])),
+            ..Default::default()
+        });
+        assert_eq!(cache.len(), 2);
+
+        cache.on_request_sent(&BaseRequest {
+            method: Method::POST,
+            uri: "/api/storage/mv".to_string(),
+            body: Bytes::from_static(br#"{"Src":"/u/dir/","Dest":"/u/other"}"#),
+            ..Default::default()
+        });
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpStreamBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::storage::fs;
+use crate::storage::mirror::{FileTracker, MirrorReport, PathFilter, walk_remote};
+use crate::storage::progress::ProgressTracker;
+use crate::storage::range::ByteRange;
+use crate::storage::transfer::PART_SIZE;
+use crate::storage::verify::{IntegrityError, mismatched_ranges, remote_digests};
+use anyhow::anyhow;
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, stream};
+use tracing::warn;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// Copies remote files and directory trees from one client to another, which
+/// may point at different zones, streaming `download` into upload/`writeAt`
+/// without touching the local disk.
+///
+/// With verification enabled the checksum API of the destination is compared
+/// with that of the source after every copied file, so data corrupted on
+/// either side of the stream is caught; mismatched blocks are copied again
+/// with `readAt` up to `max_retries` times.
+#[derive(Debug, Clone)]
+pub struct Copier {
+    src: OpenApiClient,
+    dst: OpenApiClient,
+    concurrency: usize,
+    verify: bool,
+    block_size: u64,
+    max_retries: usize,
+    progress: Option<ProgressTracker>,
+}
+
+impl Copier {
+    /// Pass the same client twice to copy within one zone.
+    pub fn new(src: OpenApiClient, dst: OpenApiClient) -> Self {
+        Self {
+            src,
+            dst,
+            concurrency: DEFAULT_CONCURRENCY,
+            verify: true,
+            block_size: DEFAULT_BLOCK_SIZE,
+            max_retries: DEFAULT_MAX_RETRIES,
+            progress: None,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Block size used for verification.
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: ProgressTracker) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Copies the file or directory `src_path` to `dst_path`, replacing
+    /// existing files. Per-file failures of a directory copy are collected in
+    /// the report; paths in the report are relative to `src_path`, or the file
+    /// name when copying a single file.
+    pub async fn copy(&self, src_path: &str, dst_path: &str) -> anyhow::Result<MirrorReport> {
+        let file_info = fs::stat(&self.src, src_path).await?;
+        let mut report = MirrorReport::default();
+
+        let files = if file_info.is_dir {
+            let filter = PathFilter::new(&[], &[])?;
+            let (dirs, files) = walk_remote(&self.src, src_path, &filter).await?;
+            fs::mkdir(&self.dst, dst_path).await?;
+            for dir in dirs {
+                if let Err(e) = fs::mkdir(&self.dst, &fs::join(dst_path, &dir)).await {
+                    report.fail(dir, e);
+                }
+            }
+            files
+                .into_iter()
+                .map(|(path, file_info)| {
+                    let src = fs::join(src_path, &path);
+                    let dst = fs::join(dst_path, &path);
+                    (path, src, dst, file_info.size as u64)
+                })
+                .collect()
+        } else {
+            vec![(
+                file_info.name.clone(),
+                src_path.to_string(),
+                dst_path.to_string(),
+                file_info.size as u64,
+            )]
+        };
+
+        if let Some(progress) = &self.progress {
+            progress.set_total(files.iter().map(|(_, _, _, size)| size).sum());
+        }
+        let outcomes: Vec<(String, anyhow::Result<Option<u64>>)> = stream::iter(files)
+            .map(|(path, src, dst, size)| async move {
+                let tracker = FileTracker::new(self.progress.as_ref(), &path, size);
+                let outcome = self.copy_file(&src, &dst, &tracker).await;
+                (path, tracker.done(outcome).map(Some))
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        for (path, outcome) in outcomes {
+            report.record(path, outcome);
+        }
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+
+        Ok(report)
+    }
+
+    async fn copy_file(
+        &self,
+        src_path: &str,
+        dst_path: &str,
+        tracker: &FileTracker,
+    ) -> anyhow::Result<u64> {
+        let http_fn = ApiStorageDownloadRequest::new()
+            .with_path(RemotePath::new(src_path)?)
+            .stream_builder();
+        let response = self.src.clone().send(http_fn).await?;
+        let mut stream = self
+            .src
+            .throttle(response.stream.ok_or_else(|| anyhow!("stream not found"))?);
+
+        let mut buffer = BytesMut::new();
+        let mut offset = 0;
+        while let Some(data) = stream.next().await {
+            buffer.extend_from_slice(&data?);
+            while buffer.len() as u64 >= PART_SIZE {
+                let part = buffer.split_to(PART_SIZE as usize).freeze();
+                self.write_part(dst_path, &mut offset, part, tracker)
+                    .await?;
+            }
+        }
+        // the first part is always sent so that empty files are created too
+        if offset == 0 || !buffer.is_empty() {
+            self.write_part(dst_path, &mut offset, buffer.freeze(), tracker)
+                .await?;
+        }
+
+        if self.verify {
+            return self.verify_file(src_path, dst_path).await;
+        }
+
+        Ok(offset)
+    }
+
+    async fn write_part(
+        &self,
+        dst_path: &str,
+        offset: &mut u64,
+        part: Bytes,
+        tracker: &FileTracker,
+    ) -> anyhow::Result<()> {
+        let length = part.len();
+        if *offset == 0 {
+            fs::upload(&self.dst, dst_path, part.to_vec(), true).await?;
+        } else {
+            fs::write_at(&self.dst, dst_path, *offset, part).await?;
+        }
+        tracker.advance(length);
+        *offset += length as u64;
+        Ok(())
+    }
+
+    /// Compares the checksums of the copy with those of the source, re-copying
+    /// mismatched blocks, and returns the size of the source.
+    async fn verify_file(&self, src_path: &str, dst_path: &str) -> anyhow::Result<u64> {
+        let reference = remote_digests(&self.src, src_path, self.block_size).await?;
+        let size = reference.last().map_or(0, |(range, _)| range.end());
+        let mut attempt = 0;
+        loop {
+            let dst_size = fs::stat(&self.dst, dst_path).await?.size as u64;
+            if dst_size != size {
+                return Err(IntegrityError::SizeMismatch {
+                    path: dst_path.to_string(),
+                    expected: size,
+                    actual: dst_size,
+                }
+                .into());
+            }
+            let actual = remote_digests(&self.dst, dst_path, self.block_size).await?;
+            let mismatched = mismatched_ranges(&reference, &actual);
+            let Some(&range) = mismatched.first() else {
+                return Ok(size);
+            };
+            if attempt == self.max_retries {
+                return Err(IntegrityError::ChecksumMismatch {
+                    path: dst_path.to_string(),
+                    range,
+                }
+                .into());
+            }
+            attempt += 1;
+            for range in mismatched {
+                warn!(
+                    "re-copying {} {} to {} after checksum mismatch",
+                    src_path, range, dst_path
+                );
+                for part in ByteRange::split(range.length, PART_SIZE) {
+                    let offset = range.offset + part.offset;
+                    let data = fs::read_at(&self.src, src_path, offset, part.length).await?;
+                    fs::write_at(&self.dst, dst_path, offset, data).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use super::*;
+    use crate::emulator::testing::TestStorage;
+
+    #[tokio::test]
+    async fn test_copy() -> anyhow::Result<()> {
+        let storage = TestStorage::start().await?;
+        let client = &storage.client;
+        fs::mkdir_all(client, "/u1/src/nested").await?;
+        fs::upload(client, "/u1/src/a.txt", b"alpha".to_vec(), true).await?;
+        fs::upload(client, "/u1/src/nested/b.txt", b"beta".to_vec(), true).await?;
+        fs::upload(client, "/u1/src/empty.txt", Vec::new(), true).await?;
+
+        let copier = Copier::new(client.clone(), client.clone()).with_block_size(4);
+        let report = copier.copy("/u1/src", "/u1/dst").await?;
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(fs::download(client, "/u1/dst/a.txt").await?, "alpha");
+        assert_eq!(fs::download(client, "/u1/dst/nested/b.txt").await?, "beta");
+        assert_eq!(fs::stat(client, "/u1/dst/empty.txt").await?.size, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_copy_repairs_corrupted_source_read() -> anyhow::Result<()> {
+        use axum::body::{Body, to_bytes};
+        use axum::extract::Request;
+        use axum::middleware::{self, Next};
+        use axum::response::Response;
+
+        // flips the first byte of the second block of every streamed download
+        let storage = TestStorage::start_with(|router| {
+            router.layer(middleware::from_fn(
+                |request: Request, next: Next| async move {
+                    let corrupt = request.uri().path() == "/api/storage/download";
+                    let response = next.run(request).await;
+                    if !corrupt {
+                        return response;
+                    }
+                    let (parts, body) = response.into_parts();
+                    let mut data = to_bytes(body, usize::MAX).await.unwrap().to_vec();
+                    data[4] ^= 0xff;
+                    Response::from_parts(parts, Body::from(data))
+                },
+            ))
+        })
+        .await?;
+        let client = &storage.client;
+        fs::upload(client, "/u1/deck.inp", b"aaaabbbbcc".to_vec(), true).await?;
+
+        let copier = Copier::new(client.clone(), client.clone()).with_block_size(4);
+        let report = copier.copy("/u1/deck.inp", "/u1/copy.inp").await?;
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        // read back with readAt, which the middleware leaves alone
+        assert_eq!(
+            fs::read_at(client, "/u1/copy.inp", 0, 10).await?,
+            "aaaabbbbcc"
+        );
+
+        let report = copier
+            .with_max_retries(0)
+            .copy("/u1/deck.inp", "/u1/copy.inp")
+            .await?;
+        assert_eq!(report.failed.len(), 1);
+        Ok(())
+    }
+}
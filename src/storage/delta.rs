@@ -0,0 +1,522 @@
+use crate::common::client::OpenApiClient;
+use crate::common::crypt::rolling::{
+    RollingChecksum, RollingHashType, chunk_checksums, strong_checksum,
+};
+use crate::model::file::ChunkChecksum;
+use crate::storage::range::ByteRange;
+use crate::storage::{fs, transfer};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tracing::debug;
+
+const DEFAULT_BLOCK_SIZE: u64 = 1024 * 1024;
+/// Bytes read at once while searching the local file for remote blocks.
+const SCAN_READ_SIZE: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DeltaReport {
+    pub file_size: u64,
+    pub transferred_bytes: u64,
+    pub changed_ranges: Vec<ByteRange>,
+}
+
+impl DeltaReport {
+    fn new(file_size: u64) -> Self {
+        Self {
+            file_size,
+            ..Default::default()
+        }
+    }
+
+    fn push(&mut self, range: ByteRange) {
+        self.transferred_bytes += range.length;
+        self.changed_ranges.push(range);
+    }
+}
+
+/// Synchronizes a local file and a remote file by comparing them with the
+/// block checksums of the checksum API and transferring only the blocks that
+/// differ.
+#[derive(Debug, Clone)]
+pub struct DeltaSync {
+    client: OpenApiClient,
+    block_size: u64,
+    rolling_hash_type: Option<isize>,
+}
+
+impl DeltaSync {
+    pub fn new(client: OpenApiClient) -> Self {
+        Self {
+            client,
+            block_size: DEFAULT_BLOCK_SIZE,
+            rolling_hash_type: None,
+        }
+    }
+
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    pub fn with_rolling_hash_type(mut self, rolling_hash_type: isize) -> Self {
+        self.rolling_hash_type = Some(rolling_hash_type);
+        self
+    }
+
+    /// Makes `remote_path` identical to `local_path`.
+    ///
+    /// This is not a delta upload: the storage API cannot copy data between
+    /// remote files, so a changed file is uploaded in full to a temp sibling
+    /// and then moved onto `remote_path`, which stays intact if that fails.
+    /// The checksums only save the upload of an unchanged file. To report
+    /// what changed, the local blocks are searched at any offset of the remote
+    /// file with `checksumsFindChunks`; `changed_ranges` lists the blocks found
+    /// nowhere, so blocks that merely moved are not counted.
+    pub async fn upload(
+        &self,
+        local_path: impl AsRef<Path>,
+        remote_path: &str,
+    ) -> anyhow::Result<DeltaReport> {
+        let local_path = local_path.as_ref();
+        let local_size = tokio::fs::metadata(local_path).await?.len();
+
+        let remote = fs::stat_if_exists(&self.client, remote_path).await?;
+        if remote.as_ref().is_some_and(|file_info| file_info.is_dir) {
+            bail!("{} is a directory", remote_path);
+        }
+        let remote_size = remote.map(|file_info| file_info.size as u64);
+        let hash_type = RollingHashType::try_from(self.rolling_hash_type())?;
+        let local = chunk_checksums(
+            File::open(local_path).await?,
+            self.block_size as usize,
+            hash_type,
+        )
+        .await?;
+
+        if remote_size == Some(local_size) {
+            let remote: HashMap<u64, ChunkChecksum> = self
+                .remote_checksums(remote_path)
+                .await?
+                .into_iter()
+                .map(|checksum| (checksum.chunk_offset as u64, checksum))
+                .collect();
+            let unchanged = local.iter().all(|checksum| {
+                remote
+                    .get(&(checksum.chunk_offset as u64))
+                    .is_some_and(|remote| {
+                        remote.size == checksum.size
+                            && remote.strong_checksum == checksum.strong_checksum
+                    })
+            });
+            if unchanged {
+                return Ok(DeltaReport::new(local_size));
+            }
+        }
+
+        let found: HashSet<(Vec<u8>, isize)> = match remote_size {
+            Some(remote_size) if remote_size > 0 && local_size > 0 => fs::find_chunks(
+                &self.client,
+                remote_path,
+                self.block_size,
+                Some(self.rolling_hash_type()),
+                local.clone(),
+            )
+            .await?
+            .into_iter()
+            .map(|chunk| (chunk.strong_checksum, chunk.length))
+            .collect(),
+            _ => HashSet::new(),
+        };
+        let mut report = DeltaReport::new(local_size);
+        report.changed_ranges = local
+            .into_iter()
+            .filter(|checksum| !found.contains(&(checksum.strong_checksum.clone(), checksum.size)))
+            .map(|checksum| ByteRange::new(checksum.chunk_offset as u64, checksum.size as u64))
+            .collect();
+        debug!(
+            "uploading {} in full, {} of its blocks changed",
+            remote_path,
+            report.changed_ranges.len()
+        );
+        report.transferred_bytes =
+            transfer::upload_file_atomic(&self.client, local_path, remote_path, true).await?;
+        Ok(report)
+    }
+
+    /// Makes `local_path` identical to `remote_path`, reading changed blocks with `readAt`.
+    ///
+    /// Like rsync, the weak checksum of every remote block is searched at any
+    /// offset of the local file and confirmed with its strong checksum, so
+    /// blocks that moved because data was inserted or removed are still
+    /// reused. The result is assembled in a temp sibling and then moved onto
+    /// `local_path`.
+    pub async fn download(
+        &self,
+        remote_path: &str,
+        local_path: impl AsRef<Path>,
+    ) -> anyhow::Result<DeltaReport> {
+        let local_path = local_path.as_ref();
+        let file_info = fs::stat(&self.client, remote_path).await?;
+        if file_info.is_dir {
+            bail!("{} is a directory", remote_path);
+        }
+        let remote_size = file_info.size as u64;
+        let checksums = self.remote_checksums(remote_path).await?;
+        let mut expected_offset = 0;
+        for checksum in &checksums {
+            if checksum.chunk_offset as u64 != expected_offset || checksum.size <= 0 {
+                bail!(
+                    "checksums of {} do not cover it block by block at {}",
+                    remote_path,
+                    expected_offset
+                );
+            }
+            expected_offset += checksum.size as u64;
+        }
+        if expected_offset != remote_size {
+            bail!(
+                "checksums of {} cover {} of {} bytes",
+                remote_path,
+                expected_offset,
+                remote_size
+            );
+        }
+
+        let mut local = match File::open(local_path).await {
+            Ok(file) => Some(file),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let found = match &mut local {
+            Some(file) => self.find_local_blocks(file, &checksums).await?,
+            None => HashMap::new(),
+        };
+
+        let temp_path = temp_sibling(local_path);
+        let assembled = self
+            .assemble(remote_path, &temp_path, local.as_mut(), &checksums, &found)
+            .await;
+        let report = match assembled {
+            Ok(report) => report,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                return Err(e);
+            }
+        };
+        tokio::fs::rename(&temp_path, local_path).await?;
+        Ok(DeltaReport {
+            file_size: remote_size,
+            ..report
+        })
+    }
+
+    async fn remote_checksums(&self, remote_path: &str) -> anyhow::Result<Vec<ChunkChecksum>> {
+        fs::chunk_checksums(
+            &self.client,
+            remote_path,
+            self.block_size,
+            Some(self.rolling_hash_type()),
+        )
+        .await
+    }
+
+    fn rolling_hash_type(&self) -> isize {
+        self.rolling_hash_type
+            .unwrap_or_else(|| RollingHashType::default().into())
+    }
+
+    /// Maps the index of every remote block found in `file` to its local offset.
+    ///
+    /// Full blocks are searched at every offset with the rolling checksum; a
+    /// block is also compared at its own offset, which still finds unchanged
+    /// blocks if the server's weak checksum differs from ours. After a match
+    /// the search continues after the matched block.
+    async fn find_local_blocks(
+        &self,
+        file: &mut File,
+        checksums: &[ChunkChecksum],
+    ) -> anyhow::Result<HashMap<usize, u64>> {
+        let hash_type = RollingHashType::try_from(self.rolling_hash_type())?;
+        let block_size = self.block_size as usize;
+        let mut by_weak: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        let mut by_offset: HashMap<u64, usize> = HashMap::new();
+        for (index, checksum) in checksums.iter().enumerate() {
+            by_offset.insert(checksum.chunk_offset as u64, index);
+            if checksum.size as usize == block_size {
+                by_weak
+                    .entry(checksum.weak_checksum.as_slice())
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        let mut found = HashMap::new();
+        let local_size = file.metadata().await?.len();
+        // a short last block can only be compared at its own offset
+        for (index, checksum) in checksums.iter().enumerate() {
+            let range = ByteRange::new(checksum.chunk_offset as u64, checksum.size as u64);
+            if checksum.size as usize != block_size && range.end() <= local_size {
+                let mut block = vec![0u8; range.length as usize];
+                file.seek(SeekFrom::Start(range.offset)).await?;
+                file.read_exact(&mut block).await?;
+                if strong_checksum(&block) == checksum.strong_checksum {
+                    found.insert(index, range.offset);
+                }
+            }
+        }
+
+        file.seek(SeekFrom::Start(0)).await?;
+        let read_size = block_size.max(SCAN_READ_SIZE);
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut buffer_offset = 0u64;
+        let mut start = 0;
+        let mut eof = false;
+        let mut rolling: Option<RollingChecksum> = None;
+        loop {
+            // keep the window and the byte after it in the buffer
+            if buffer.len() < start + block_size + 1 && !eof {
+                buffer.drain(..start);
+                buffer_offset += start as u64;
+                start = 0;
+                let filled = buffer.len();
+                buffer.resize(filled + read_size, 0);
+                let n = file.read(&mut buffer[filled..]).await?;
+                buffer.truncate(filled + n);
+                eof = n == 0;
+                continue;
+            }
+            if buffer.len() < start + block_size {
+                break;
+            }
+
+            let window = &buffer[start..start + block_size];
+            let offset = buffer_offset + start as u64;
+            let weak = rolling
+                .get_or_insert_with(|| {
+                    let mut rolling = RollingChecksum::new(hash_type);
+                    rolling.update(window);
+                    rolling
+                })
+                .digest_bytes();
+            let candidates: Vec<usize> = by_weak
+                .get(weak.as_slice())
+                .into_iter()
+                .flatten()
+                .copied()
+                .chain(by_offset.get(&offset).copied())
+                .filter(|index| {
+                    !found.contains_key(index) && checksums[*index].size as usize == block_size
+                })
+                .collect();
+            let mut matched = false;
+            if !candidates.is_empty() {
+                let strong = strong_checksum(window);
+                for index in candidates {
+                    if checksums[index].strong_checksum == strong {
+                        found.insert(index, offset);
+                        matched = true;
+                    }
+                }
+            }
+
+            if matched {
+                start += block_size;
+                rolling = None;
+            } else if let (Some(rolling), Some(&input)) =
+                (rolling.as_mut(), buffer.get(start + block_size))
+            {
+                rolling.roll(buffer[start], input);
+                start += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Writes the remote file to `temp_path`, copying found blocks from
+    /// `local` and reading the others with `readAt`.
+    async fn assemble(
+        &self,
+        remote_path: &str,
+        temp_path: &Path,
+        mut local: Option<&mut File>,
+        checksums: &[ChunkChecksum],
+        found: &HashMap<usize, u64>,
+    ) -> anyhow::Result<DeltaReport> {
+        let mut output = File::create(temp_path).await?;
+        let mut report = DeltaReport::default();
+        for (index, checksum) in checksums.iter().enumerate() {
+            let range = ByteRange::new(checksum.chunk_offset as u64, checksum.size as u64);
+            match (found.get(&index), local.as_deref_mut()) {
+                (Some(&local_offset), Some(file)) => {
+                    let mut block = vec![0u8; range.length as usize];
+                    file.seek(SeekFrom::Start(local_offset)).await?;
+                    file.read_exact(&mut block).await?;
+                    output.write_all(&block).await?;
+                }
+                _ => {
+                    let data =
+                        fs::read_at(&self.client, remote_path, range.offset, range.length).await?;
+                    output.write_all(&data).await?;
+                    report.push(range);
+                }
+            }
+        }
+        output.flush().await?;
+        Ok(report)
+    }
+}
+
+fn temp_sibling(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.delta", name, std::process::id()))
+}
+
+#[cfg(all(test, feature = "emulator"))]
+mod tests {
+    use super::*;
+    use crate::emulator::testing::TestStorage;
+
+    #[tokio::test]
+    async fn test_upload() -> anyhow::Result<()> {
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let fail_move = Arc::new(AtomicBool::new(false));
+        let fail_move_ref = fail_move.clone();
+        let storage = TestStorage::start_with(move |router| {
+            router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let fail_move = fail_move_ref.clone();
+                async move {
+                    if request.uri().path() == "/api/storage/mv"
+                        && fail_move.load(Ordering::Relaxed)
+                    {
+                        return (StatusCode::SERVICE_UNAVAILABLE, "unavailable").into_response();
+                    }
+                    next.run(request).await
+                }
+            }))
+        })
+        .await?;
+        let delta = DeltaSync::new(storage.client.clone()).with_block_size(4);
+        let local_path = storage.root.join("deck.inp");
+        let remote = || fs::download(&storage.client, "/u1/deck.inp");
+
+        tokio::fs::write(&local_path, "aaaabbbbcccc").await?;
+        let report = delta.upload(&local_path, "/u1/deck.inp").await?;
+        assert_eq!(report.transferred_bytes, 12);
+        assert_eq!(report.changed_ranges.len(), 3);
+        assert_eq!(remote().await?, "aaaabbbbcccc");
+
+        let report = delta.upload(&local_path, "/u1/deck.inp").await?;
+        assert_eq!(report.transferred_bytes, 0);
+        assert!(report.changed_ranges.is_empty());
+
+        // the moved blocks are found, only the inserted one is reported
+        tokio::fs::write(&local_path, "XXXXaaaabbbbcccc").await?;
+        let report = delta.upload(&local_path, "/u1/deck.inp").await?;
+        assert_eq!(report.changed_ranges, [ByteRange::new(0, 4)]);
+        assert_eq!(report.transferred_bytes, 16);
+        assert_eq!(remote().await?, "XXXXaaaabbbbcccc");
+
+        tokio::fs::write(&local_path, "aaaaXb").await?;
+        delta.upload(&local_path, "/u1/deck.inp").await?;
+        assert_eq!(remote().await?, "aaaaXb");
+
+        // a failed upload leaves the remote file as it was, without a temp file
+        fail_move.store(true, Ordering::Relaxed);
+        tokio::fs::write(&local_path, "bbbbaaaa").await?;
+        assert!(delta.upload(&local_path, "/u1/deck.inp").await.is_err());
+        assert_eq!(remote().await?, "aaaaXb");
+        let names: Vec<String> = fs::list_dir(&storage.client, "/u1")
+            .await?
+            .into_iter()
+            .map(|file_info| file_info.name)
+            .collect();
+        assert_eq!(names, ["deck.inp"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upload_fails_when_stat_fails() -> anyhow::Result<()> {
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let writes = Arc::new(AtomicUsize::new(0));
+        let writes_ref = writes.clone();
+        let storage = TestStorage::start_with(move |router| {
+            router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let writes = writes_ref.clone();
+                async move {
+                    match request.uri().path() {
+                        "/api/storage/stat" | "/api/storage/lsWithPage" => {
+                            return (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                                .into_response();
+                        }
+                        "/api/storage/writeAt" | "/api/storage/upload/file" => {
+                            writes.fetch_add(1, Ordering::Relaxed);
+                        }
+                        _ => {}
+                    }
+                    next.run(request).await
+                }
+            }))
+        })
+        .await?;
+        let local_path = storage.root.join("deck.inp");
+        tokio::fs::write(&local_path, "aaaabbbbcccc").await?;
+
+        let delta = DeltaSync::new(storage.client.clone()).with_block_size(4);
+        assert!(delta.upload(&local_path, "/u1/deck.inp").await.is_err());
+        assert_eq!(writes.load(Ordering::Relaxed), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download() -> anyhow::Result<()> {
+        let storage = TestStorage::start().await?;
+        fs::upload(
+            &storage.client,
+            "/u1/deck.inp",
+            b"abcdefghijklmn".to_vec(),
+            false,
+        )
+        .await?;
+        let delta = DeltaSync::new(storage.client.clone()).with_block_size(4);
+        let local_path = storage.root.join("deck.inp");
+
+        // every full block moved by one byte, the short last block changed
+        tokio::fs::write(&local_path, "Xabcdefghijkl").await?;
+        let report = delta.download("/u1/deck.inp", &local_path).await?;
+        assert_eq!(report.changed_ranges, [ByteRange::new(12, 2)]);
+        assert_eq!(tokio::fs::read(&local_path).await?, b"abcdefghijklmn");
+
+        tokio::fs::write(&local_path, "abcdZZZZijklmn").await?;
+        let report = delta.download("/u1/deck.inp", &local_path).await?;
+        assert_eq!(report.changed_ranges, [ByteRange::new(4, 4)]);
+        assert_eq!(tokio::fs::read(&local_path).await?, b"abcdefghijklmn");
+
+        tokio::fs::remove_file(&local_path).await?;
+        let report = delta.download("/u1/deck.inp", &local_path).await?;
+        assert_eq!(report.transferred_bytes, 14);
+        assert_eq!(tokio::fs::read(&local_path).await?, b"abcdefghijklmn");
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+use crate::common::client::OpenApiClient;
+use crate::storage::fs;
+use crate::storage::mirror::{PathFilter, walk_local, walk_remote};
+use crate::storage::verify::{BlockHasher, mismatched_ranges, remote_digests};
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Size and modification time of one side of a diff entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryInfo {
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffEntry {
+    /// Path relative to the compared roots, using `/` as separator.
+    pub path: String,
+    pub local: Option<EntryInfo>,
+    pub remote: Option<EntryInfo>,
+}
+
+/// What pushing the local directory would change on the remote side.
+///
+/// Every path is listed under at most one category, checked in field order;
+/// `content_changed` is only filled when content comparison is enabled.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffReport {
+    /// Only present locally.
+    pub added: Vec<DiffEntry>,
+    /// Only present remotely.
+    pub removed: Vec<DiffEntry>,
+    /// A file on one side and a directory on the other.
+    pub type_changed: Vec<DiffEntry>,
+    pub size_changed: Vec<DiffEntry>,
+    /// Same size, but the checksum API reports different blocks.
+    pub content_changed: Vec<DiffEntry>,
+    /// Same size (and content when compared), but modified at a different second.
+    pub mtime_changed: Vec<DiffEntry>,
+}
+
+impl DiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.type_changed.is_empty()
+            && self.size_changed.is_empty()
+            && self.content_changed.is_empty()
+            && self.mtime_changed.is_empty()
+    }
+}
+
+/// One line per entry, e.g. `+ case/mesh.inp`, followed by a count per category.
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let size = |info: &Option<EntryInfo>| info.as_ref().map_or(0, |info| info.size);
+        for entry in &self.added {
+            writeln!(f, "+ {}", entry.path)?;
+        }
+        for entry in &self.removed {
+            writeln!(f, "- {}", entry.path)?;
+        }
+        for entry in &self.type_changed {
+            writeln!(f, "T {}", entry.path)?;
+        }
+        for entry in &self.size_changed {
+            writeln!(
+                f,
+                "S {} ({} -> {} bytes)",
+                entry.path,
+                size(&entry.remote),
+                size(&entry.local)
+            )?;
+        }
+        for entry in &self.content_changed {
+            writeln!(f, "C {}", entry.path)?;
+        }
+        for entry in &self.mtime_changed {
+            writeln!(f, "M {}", entry.path)?;
+        }
+        write!(
+            f,
+            "{} added, {} removed, {} type changed, {} size changed, {} content changed, {} mtime changed",
+            self.added.len(),
+            self.removed.len(),
+            self.type_changed.len(),
+            self.size_changed.len(),
+            self.content_changed.len(),
+            self.mtime_changed.len()
+        )
+    }
+}
+
+/// Compares a local directory tree with a remote one without transferring
+/// anything. Include and exclude globs work as in
+/// [`Mirror`](crate::storage::mirror::Mirror).
+#[derive(Debug, Clone)]
+pub struct Diff {
+    client: OpenApiClient,
+    local_dir: PathBuf,
+    remote_dir: String,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    compare_content: bool,
+    block_size: u64,
+    concurrency: usize,
+}
+
+pub fn diff(client: &OpenApiClient, local_dir: impl AsRef<Path>, remote_dir: &str) -> Diff {
+    Diff {
+        client: client.clone(),
+        local_dir: local_dir.as_ref().to_path_buf(),
+        remote_dir: remote_dir.to_string(),
+        include: Vec::new(),
+        exclude: Vec::new(),
+        compare_content: false,
+        block_size: DEFAULT_BLOCK_SIZE,
+        concurrency: DEFAULT_CONCURRENCY,
+    }
+}
+
+impl Diff {
+    pub fn with_include(mut self, pattern: String) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    pub fn with_exclude(mut self, pattern: String) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Compares files of equal size block by block against the checksum API.
+    pub fn with_compare_content(mut self, compare_content: bool) -> Self {
+        self.compare_content = compare_content;
+        self
+    }
+
+    pub fn with_block_size(mut self, block_size: u64) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// Number of files whose content is compared at once.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn run(self) -> anyhow::Result<DiffReport> {
+        let filter = PathFilter::new(&self.include, &self.exclude)?;
+
+        let (dirs, files) = walk_local(&self.local_dir, &filter).await?;
+        let mut local = BTreeMap::new();
+        for dir in dirs {
+            local.insert(dir, dir_info());
+        }
+        for (path, metadata) in files {
+            let info = EntryInfo {
+                is_dir: false,
+                size: metadata.len(),
+                modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            };
+            local.insert(path, info);
+        }
+
+        // a remote directory that does not exist yet is diffed as an empty tree
+        let (dirs, files) = match fs::stat_if_exists(&self.client, &self.remote_dir).await? {
+            Some(_) => walk_remote(&self.client, &self.remote_dir, &filter).await?,
+            None => Default::default(),
+        };
+        let mut remote = BTreeMap::new();
+        for dir in dirs {
+            remote.insert(dir, dir_info());
+        }
+        for (path, file_info) in files {
+            let info = EntryInfo {
+                is_dir: false,
+                size: file_info.size as u64,
+                modified: file_info.modified(),
+            };
+            remote.insert(path, info);
+        }
+
+        let mut report = compare(&local, &remote);
+        if self.compare_content {
+            self.compare_content(&mut report, &local, &remote).await?;
+        }
+        Ok(report)
+    }
+
+    async fn compare_content(
+        &self,
+        report: &mut DiffReport,
+        local: &BTreeMap<String, EntryInfo>,
+        remote: &BTreeMap<String, EntryInfo>,
+    ) -> anyhow::Result<()> {
+        let candidates = local.iter().filter(|(path, info)| {
+            !info.is_dir
+                && remote
+                    .get(*path)
+                    .is_some_and(|remote| !remote.is_dir && remote.size == info.size)
+        });
+        let changed: Vec<anyhow::Result<Option<String>>> = stream::iter(candidates)
+            .map(|(path, _)| async move {
+                let same = self.same_content(path).await?;
+                Ok((!same).then(|| path.clone()))
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        for path in changed {
+            let Some(path) = path? else {
+                continue;
+            };
+            report.mtime_changed.retain(|entry| entry.path != path);
+            report.content_changed.push(DiffEntry {
+                local: local.get(&path).cloned(),
+                remote: remote.get(&path).cloned(),
+                path,
+            });
+        }
+        report.content_changed.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(())
+    }
+
+    async fn same_content(&self, path: &str) -> anyhow::Result<bool> {
+        let mut file = File::open(self.local_dir.join(path)).await?;
+        let mut hasher = BlockHasher::new(self.block_size);
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        loop {
+            let n = file.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        let (local, _) = hasher.finish();
+        let remote_path = fs::join(&self.remote_dir, path);
+        let remote = remote_digests(&self.client, &remote_path, self.block_size).await?;
+        Ok(mismatched_ranges(&local, &remote).is_empty())
+    }
+}
+
+fn dir_info() -> EntryInfo {
+    EntryInfo {
+        is_dir: true,
+        size: 0,
+        modified: None,
+    }
+}
+
+/// Classifies every path by metadata alone; entries are sorted by path.
+fn compare(
+    local: &BTreeMap<String, EntryInfo>,
+    remote: &BTreeMap<String, EntryInfo>,
+) -> DiffReport {
+    let mut report = DiffReport::default();
+    let entry = |path: &String| DiffEntry {
+        path: path.clone(),
+        local: local.get(path).cloned(),
+        remote: remote.get(path).cloned(),
+    };
+    for (path, local_info) in local {
+        let Some(remote_info) = remote.get(path) else {
+            report.added.push(entry(path));
+            continue;
+        };
+        if local_info.is_dir != remote_info.is_dir {
+            report.type_changed.push(entry(path));
+        } else if local_info.is_dir {
+            continue;
+        } else if local_info.size != remote_info.size {
+            report.size_changed.push(entry(path));
+        } else if local_info.modified.map(|t| t.timestamp())
+            != remote_info.modified.map(|t| t.timestamp())
+        {
+            report.mtime_changed.push(entry(path));
+        }
+    }
+    for path in remote.keys() {
+        if !local.contains_key(path) {
+            report.removed.push(entry(path));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(size: u64, secs: i64) -> EntryInfo {
+        EntryInfo {
+            is_dir: false,
+            size,
+            modified: DateTime::from_timestamp(secs, 0),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let local = BTreeMap::from([
+            ("a.txt".to_string(), file(1, 100)),
+            ("b.txt".to_string(), file(2, 100)),
+            ("c.txt".to_string(), file(3, 100)),
+            ("d".to_string(), dir_info()),
+            ("same.txt".to_string(), file(4, 100)),
+        ]);
+        let remote = BTreeMap::from([
+            ("b.txt".to_string(), file(5, 100)),
+            ("c.txt".to_string(), file(3, 200)),
+            ("d".to_string(), file(0, 100)),
+            ("e.txt".to_string(), file(6, 100)),
+            ("same.txt".to_string(), file(4, 100)),
+        ]);
+        let report = compare(&local, &remote);
+        let paths = |entries: &[DiffEntry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.path.clone()).collect()
+        };
+        assert_eq!(paths(&report.added), ["a.txt"]);
+        assert_eq!(paths(&report.removed), ["e.txt"]);
+        assert_eq!(paths(&report.type_changed), ["d"]);
+        assert_eq!(paths(&report.size_changed), ["b.txt"]);
+        assert_eq!(paths(&report.mtime_changed), ["c.txt"]);
+        assert!(report.content_changed.is_empty());
+        assert_eq!(report.size_changed[0].remote.as_ref().unwrap().size, 5);
+
+        let summary = report.to_string();
+        assert!(summary.contains("S b.txt (5 -> 2 bytes)"));
+        assert!(summary.ends_with(
+            "1 added, 1 removed, 1 type changed, 1 size changed, 0 content changed, 1 mtime changed"
+        ));
+
+        let json = serde_json::to_string(&report).unwrap();
+        let decoded: DiffReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, report);
+        assert!(compare(&local, &local).is_empty());
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_diff_missing_remote_dir() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+
+        let storage = TestStorage::start().await?;
+        let local_dir = storage.root.join("local");
+        tokio::fs::create_dir_all(local_dir.join("case")).await?;
+        tokio::fs::write(local_dir.join("case/mesh.inp"), "mesh").await?;
+
+        let report = diff(&storage.client, &local_dir, "/u1/missing/dir")
+            .with_compare_content(true)
+            .run()
+            .await?;
+        let added: Vec<&str> = report
+            .added
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(added, ["case", "case/mesh.inp"]);
+        assert!(report.removed.is_empty() && report.size_changed.is_empty());
+        Ok(())
+    }
+}
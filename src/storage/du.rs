@@ -0,0 +1,377 @@
+use crate::common::client::OpenApiClient;
+use crate::common::remote_path::RemotePath;
+use crate::model::file::FileInfo;
+use crate::storage::walk::walk;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fmt;
+
+const DEFAULT_TOP: usize = 10;
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_AGE_BUCKETS: [u64; 5] = [1, 7, 30, 90, 365];
+
+/// Recursive totals of one directory.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirUsage {
+    pub path: String,
+    pub size: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileUsage {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Files last modified at least `min_age_days` and less than `max_age_days`
+/// ago; the last bucket has no upper bound.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgeBucket {
+    pub min_age_days: u64,
+    pub max_age_days: Option<u64>,
+    pub file_count: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuReport {
+    pub generated_at: DateTime<Utc>,
+    /// Totals of the root directory.
+    pub total: DirUsage,
+    /// Every directory below the root, sorted by path.
+    pub directories: Vec<DirUsage>,
+    /// The largest directories below the root, largest first.
+    pub largest_directories: Vec<DirUsage>,
+    /// The largest files, largest first.
+    pub largest_files: Vec<FileUsage>,
+    pub age_histogram: Vec<AgeBucket>,
+    /// Files whose `mod_time` could not be parsed, left out of the histogram.
+    pub undated_file_count: u64,
+}
+
+impl fmt::Display for DuReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} bytes in {} files and {} directories",
+            self.total.path, self.total.size, self.total.file_count, self.total.dir_count
+        )?;
+        writeln!(f, "largest directories:")?;
+        for dir in &self.largest_directories {
+            writeln!(
+                f,
+                "  {:>14}  {} ({} files)",
+                dir.size, dir.path, dir.file_count
+            )?;
+        }
+        writeln!(f, "largest files:")?;
+        for file in &self.largest_files {
+            writeln!(f, "  {:>14}  {}", file.size, file.path)?;
+        }
+        writeln!(f, "age:")?;
+        for bucket in &self.age_histogram {
+            let range = match bucket.max_age_days {
+                Some(max_age_days) => format!("{}-{}d", bucket.min_age_days, max_age_days),
+                None => format!("{}d+", bucket.min_age_days),
+            };
+            writeln!(
+                f,
+                "  {:>9}  {} files, {} bytes",
+                range, bucket.file_count, bucket.size
+            )?;
+        }
+        write!(f, "  {:>9}  {} files", "undated", self.undated_file_count)
+    }
+}
+
+/// Totals `FileInfo::size` per directory of a remote tree, like `du`.
+#[derive(Debug, Clone)]
+pub struct Du {
+    client: OpenApiClient,
+    root: String,
+    top: usize,
+    age_buckets: Vec<u64>,
+    concurrency: usize,
+}
+
+pub fn du(client: &OpenApiClient, path: &str) -> Du {
+    Du {
+        client: client.clone(),
+        root: path.to_string(),
+        top: DEFAULT_TOP,
+        age_buckets: DEFAULT_AGE_BUCKETS.to_vec(),
+        concurrency: DEFAULT_CONCURRENCY,
+    }
+}
+
+impl Du {
+    /// Number of largest files and directories to report.
+    pub fn with_top(mut self, top: usize) -> Self {
+        self.top = top;
+        self
+    }
+
+    /// Upper bounds in days of the age histogram buckets, e.g. `[1, 7, 30]`
+    /// for under a day, under a week, under a month and older.
+    pub fn with_age_buckets(mut self, mut age_buckets: Vec<u64>) -> Self {
+        age_buckets.sort_unstable();
+        age_buckets.dedup();
+        self.age_buckets = age_buckets;
+        self
+    }
+
+    /// Number of directories listed at once.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn run(self) -> anyhow::Result<DuReport> {
+        let root = RemotePath::new(&self.root)?.to_string();
+        let mut usage = Usage::new(&root, self.top, &self.age_buckets, Utc::now());
+        walk(&self.client, &root)
+            .with_concurrency(self.concurrency)
+            .into_stream()
+            .try_for_each(|(path, file_info)| {
+                usage.add(&path, &file_info);
+                futures::future::ready(Ok(()))
+            })
+            .await?;
+        Ok(usage.finish())
+    }
+}
+
+/// Ordered so that the smallest file, then the greatest path, is popped first.
+type RankedFile = Reverse<(u64, Reverse<String>, Option<DateTime<Utc>>)>;
+
+struct Usage {
+    root: String,
+    top: usize,
+    now: DateTime<Utc>,
+    dirs: BTreeMap<String, DirUsage>,
+    largest_files: BinaryHeap<RankedFile>,
+    age_histogram: Vec<AgeBucket>,
+    undated_file_count: u64,
+}
+
+impl Usage {
+    fn new(root: &str, top: usize, age_buckets: &[u64], now: DateTime<Utc>) -> Self {
+        let mut min_age_days = 0;
+        let mut age_histogram = Vec::new();
+        for max_age_days in age_buckets.iter().copied().map(Some).chain([None]) {
+            age_histogram.push(AgeBucket {
+                min_age_days,
+                max_age_days,
+                file_count: 0,
+                size: 0,
+            });
+            min_age_days = max_age_days.unwrap_or_default();
+        }
+        let total = DirUsage {
+            path: root.to_string(),
+            ..Default::default()
+        };
+        Self {
+            root: root.to_string(),
+            top,
+            now,
+            dirs: BTreeMap::from([(root.to_string(), total)]),
+            largest_files: BinaryHeap::new(),
+            age_histogram,
+            undated_file_count: 0,
+        }
+    }
+
+    fn add(&mut self, path: &str, file_info: &FileInfo) {
+        for ancestor in self.ancestors(path) {
+            let dir = self
+                .dirs
+                .entry(ancestor.to_string())
+                .or_insert_with(|| DirUsage {
+                    path: ancestor.to_string(),
+                    ..Default::default()
+                });
+            if file_info.is_dir {
+                dir.dir_count += 1;
+            } else {
+                dir.size += file_info.size as u64;
+                dir.file_count += 1;
+            }
+        }
+        if file_info.is_dir {
+            self.dirs
+                .entry(path.to_string())
+                .or_insert_with(|| DirUsage {
+                    path: path.to_string(),
+                    ..Default::default()
+                });
+            return;
+        }
+
+        let size = file_info.size as u64;
+        let modified = file_info.modified();
+        match modified {
+            Some(modified) => {
+                let age_days = (self.now - modified).num_days().max(0) as u64;
+                if let Some(bucket) = self
+                    .age_histogram
+                    .iter_mut()
+                    .find(|bucket| bucket.max_age_days.is_none_or(|max| age_days < max))
+                {
+                    bucket.file_count += 1;
+                    bucket.size += size;
+                }
+            }
+            None => self.undated_file_count += 1,
+        }
+        if self.top > 0 {
+            self.largest_files
+                .push(Reverse((size, Reverse(path.to_string()), modified)));
+            if self.largest_files.len() > self.top {
+                self.largest_files.pop();
+            }
+        }
+    }
+
+    /// The directories containing `path`, from its parent up to the root.
+    fn ancestors<'a>(&self, path: &'a str) -> Vec<&'a str> {
+        let mut ancestors = Vec::new();
+        let mut current = path;
+        while let Some((parent, _)) = current.rsplit_once('/') {
+            let parent = if parent.is_empty() { "/" } else { parent };
+            if parent.len() < self.root.len() {
+                break;
+            }
+            ancestors.push(parent);
+            if parent == "/" {
+                break;
+            }
+            current = parent;
+        }
+        ancestors
+    }
+
+    fn finish(mut self) -> DuReport {
+        let total = self.dirs.remove(&self.root).unwrap_or_default();
+        let directories: Vec<DirUsage> = self.dirs.into_values().collect();
+        let mut largest_directories = directories.clone();
+        largest_directories.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        largest_directories.truncate(self.top);
+        let largest_files = self
+            .largest_files
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((size, Reverse(path), modified))| FileUsage {
+                path,
+                size,
+                modified,
+            })
+            .collect();
+        DuReport {
+            generated_at: self.now,
+            total,
+            directories,
+            largest_directories,
+            largest_files,
+            age_histogram: self.age_histogram,
+            undated_file_count: self.undated_file_count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: isize, mod_time: &str, is_dir: bool) -> FileInfo {
+        FileInfo {
+            size,
+            mod_time: mod_time.to_string(),
+            is_dir,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_usage() {
+        let now = DateTime::parse_from_rfc3339("2025-03-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let mut usage = Usage::new("/u1/work", 2, &[1, 30], now);
+        usage.add("/u1/work/a", &entry(0, "", true));
+        usage.add(
+            "/u1/work/a/x.dat",
+            &entry(100, "2025-02-28T12:00:00Z", false),
+        );
+        usage.add("/u1/work/a/b", &entry(0, "", true));
+        usage.add(
+            "/u1/work/a/b/y.dat",
+            &entry(300, "2025-02-10T00:00:00Z", false),
+        );
+        usage.add("/u1/work/empty", &entry(0, "", true));
+        usage.add("/u1/work/z.dat", &entry(50, "2024-01-01T00:00:00Z", false));
+        usage.add("/u1/work/undated.dat", &entry(1, "yesterday", false));
+        let report = usage.finish();
+
+        assert_eq!(
+            report.total,
+            DirUsage {
+                path: "/u1/work".to_string(),
+                size: 451,
+                file_count: 4,
+                dir_count: 3,
+            }
+        );
+        let dir = |path: &str| {
+            report
+                .directories
+                .iter()
+                .find(|dir| dir.path == path)
+                .unwrap()
+        };
+        assert_eq!(
+            (dir("/u1/work/a").size, dir("/u1/work/a").file_count),
+            (400, 2)
+        );
+        assert_eq!(dir("/u1/work/a").dir_count, 1);
+        assert_eq!(dir("/u1/work/a/b").size, 300);
+        assert_eq!(dir("/u1/work/empty").size, 0);
+
+        let paths: Vec<&str> = report
+            .largest_directories
+            .iter()
+            .map(|dir| dir.path.as_str())
+            .collect();
+        assert_eq!(paths, ["/u1/work/a", "/u1/work/a/b"]);
+        let paths: Vec<&str> = report
+            .largest_files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(paths, ["/u1/work/a/b/y.dat", "/u1/work/a/x.dat"]);
+
+        let counts: Vec<(u64, u64)> = report
+            .age_histogram
+            .iter()
+            .map(|bucket| (bucket.file_count, bucket.size))
+            .collect();
+        assert_eq!(counts, [(1, 100), (1, 300), (1, 50)]);
+        assert_eq!(report.age_histogram[2].max_age_days, None);
+        assert_eq!(report.undated_file_count, 1);
+
+        let json = serde_json::to_string(&report).unwrap();
+        let decoded: DuReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, report);
+        assert!(
+            report
+                .to_string()
+                .starts_with("/u1/work: 451 bytes in 4 files and 3 directories")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use crate::api::v1::storage::api_storage_check_sums_find_chunk::ApiStorageCheckSumsFindChunkRequest;
+use crate::api::v1::storage::api_storage_chunk_check_sums::ApiStorageChunkCheckSumsRequest;
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::api::v1::storage::api_storage_list::ApiStorageListRequest;
+use crate::api::v1::storage::api_storage_mkdir::ApiStorageMkDirRequest;
+use crate::api::v1::storage::api_storage_move::ApiStorageMoveRequest;
+use crate::api::v1::storage::api_storage_read_at::ApiStorageReadAtRequest;
+use crate::api::v1::storage::api_storage_remove::ApiStorageRemoveRequest;
+use crate::api::v1::storage::api_storage_stat::ApiStorageStatRequest;
+use crate::api::v1::storage::api_storage_truncate::ApiStorageTruncateRequest;
+use crate::api::v1::storage::api_storage_upload::ApiStorageUploadRequest;
+use crate::api::v1::storage::api_storage_write_at::ApiStorageWriteAtRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::model::file::{Chunk, ChunkChecksum, FileInfo};
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+
+const LIST_PAGE_SIZE: isize = 1000;
+
+pub async fn stat(client: &OpenApiClient, path: &str) -> anyhow::Result<FileInfo> {
+    let http_fn = ApiStorageStatRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .builder();
+    let response = client.clone().send(http_fn).await?;
+    response
+        .into_data()?
+        .and_then(|data| data.file)
+        .ok_or_else(|| anyhow!("file not found: {}", path))
+}
+
+/// Like [`stat`], but returns `None` when the path does not exist.
+///
+/// A failed `stat` is confirmed by listing the parent directory, so network
+/// and permission errors are still reported as errors.
+pub async fn stat_if_exists(
+    client: &OpenApiClient,
+    path: &str,
+) -> anyhow::Result<Option<FileInfo>> {
+    let stat_error = match stat(client, path).await {
+        Ok(file_info) => return Ok(Some(file_info)),
+        Err(e) => e,
+    };
+    let path = RemotePath::new(path)?;
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(stat_error);
+    };
+    match list_dir(client, parent.as_str()).await {
+        Ok(files) => Ok(files.into_iter().find(|file_info| file_info.name == name)),
+        Err(e) => match Box::pin(stat_if_exists(client, parent.as_str())).await? {
+            None => Ok(None),
+            Some(_) => Err(e),
+        },
+    }
+}
+
+pub async fn read_at(
+    client: &OpenApiClient,
+    path: &str,
+    offset: u64,
+    length: u64,
+) -> anyhow::Result<Bytes> {
+    let http_fn = ApiStorageReadAtRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_offset(offset as isize)
+        .with_length(length as isize)
+        .builder();
+    let response = client.clone().send(http_fn).await?;
+    let data = response.data.unwrap_or_default();
+    if let Some(bandwidth_limiter) = client.bandwidth_limiter() {
+        bandwidth_limiter.acquire(data.len() as u64).await;
+    }
+    if data.len() as u64 != length {
+        bail!(
+            "read {} at {} returned {} of {} bytes",
+            path,
+            offset,
+            data.len(),
+            length
+        );
+    }
+    Ok(data)
+}
+
+/// Downloads a whole file into memory; meant for small files such as manifests.
+pub async fn download(client: &OpenApiClient, path: &str) -> anyhow::Result<Bytes> {
+    let http_fn = ApiStorageDownloadRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .builder();
+    let response = client.clone().send(http_fn).await?;
+    Ok(response.data.unwrap_or_default())
+}
+
+pub async fn write_at(
+    client: &OpenApiClient,
+    path: &str,
+    offset: u64,
+    data: Bytes,
+) -> anyhow::Result<()> {
+    let http_fn = ApiStorageWriteAtRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_offset(offset as isize)
+        .with_length(data.len() as isize)
+        .with_data(data)
+        .builder();
+    client.clone().send(http_fn).await?.into_data()?;
+    Ok(())
+}
+
+pub async fn truncate(client: &OpenApiClient, path: &str) -> anyhow::Result<()> {
+    let http_fn = ApiStorageTruncateRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .builder();
+    client.clone().send(http_fn).await?.into_data()?;
+    Ok(())
+}
+
+pub async fn chunk_checksums(
+    client: &OpenApiClient,
+    path: &str,
+    block_size: u64,
+    rolling_hash_type: Option<isize>,
+) -> anyhow::Result<Vec<ChunkChecksum>> {
+    let mut request = ApiStorageChunkCheckSumsRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_block_size(block_size as isize);
+    if let Some(rolling_hash_type) = rolling_hash_type {
+        request = request.with_rolling_hash_type(rolling_hash_type);
+    }
+    let response = client.clone().send(request.builder()).await?;
+    Ok(response
+        .into_data()?
+        .and_then(|data| data.checksums)
+        .unwrap_or_default())
+}
+
+/// Searches a remote file at every offset for blocks with the given weak and
+/// strong checksums and returns where they were found.
+pub async fn find_chunks(
+    client: &OpenApiClient,
+    path: &str,
+    block_size: u64,
+    rolling_hash_type: Option<isize>,
+    checksums: Vec<ChunkChecksum>,
+) -> anyhow::Result<Vec<Chunk>> {
+    let mut request = ApiStorageCheckSumsFindChunkRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_block_size(block_size as isize)
+        .with_chunks(checksums);
+    if let Some(rolling_hash_type) = rolling_hash_type {
+        request = request.with_rolling_hash_type(rolling_hash_type);
+    }
+    let response = client.clone().send(request.builder()).await?;
+    Ok(response
+        .into_data()?
+        .and_then(|data| data.chunks)
+        .unwrap_or_default())
+}
+
+pub async fn list_dir(client: &OpenApiClient, path: &str) -> anyhow::Result<Vec<FileInfo>> {
+    let mut files = Vec::new();
+    let mut page_offset = 0;
+    loop {
+        let http_fn = ApiStorageListRequest::new()
+            .with_path(RemotePath::new(path)?)
+            .with_page_offset(page_offset)
+            .with_page_size(LIST_PAGE_SIZE)
+            .builder();
+        let response = client.clone().send(http_fn).await?;
+        let Some(page) = response.into_data()? else {
+            break;
+        };
+        let count = page.files.len() as isize;
+        files.extend(page.files);
+        if count < LIST_PAGE_SIZE
+            || page.next_marker <= page_offset
+            || files.len() as isize >= page.total
+        {
+            break;
+        }
+        page_offset = page.next_marker;
+    }
+    Ok(files)
+}
+
+pub async fn mkdir(client: &OpenApiClient, path: &str) -> anyhow::Result<()> {
+    let http_fn = ApiStorageMkDirRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_ignore_exist(true)
+        .builder();
+    client.clone().send(http_fn).await?.into_data()?;
+    Ok(())
+}
+
+/// Creates `path` and every missing ancestor below the user root.
+pub async fn mkdir_all(client: &OpenApiClient, path: &str) -> anyhow::Result<()> {
+    let path = RemotePath::new(path)?;
+    let mut ancestors = Vec::new();
+    let mut current = Some(path);
+    while let Some(dir) = current {
+        current = dir.parent();
+        // the user root always exists
+        if current
+            .as_ref()
+            .is_some_and(|parent| parent.as_str() != "/")
+        {
+            ancestors.push(dir);
+        }
+    }
+    for dir in ancestors.iter().rev() {
+        mkdir(client, dir.as_str()).await?;
+    }
+    Ok(())
+}
+
+pub async fn upload(
+    client: &OpenApiClient,
+    path: &str,
+    content: Vec<u8>,
+    overwrite: bool,
+) -> anyhow::Result<()> {
+    let http_fn = ApiStorageUploadRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_content(content)
+        .with_overwrite(overwrite)
+        .builder();
+    client.clone().send(http_fn).await?.into_data()?;
+    Ok(())
+}
+
+/// Removes a file or directory, succeeding when it does not exist.
+pub async fn remove(client: &OpenApiClient, path: &str) -> anyhow::Result<()> {
+    let http_fn = ApiStorageRemoveRequest::new()
+        .with_path(RemotePath::new(path)?)
+        .with_ignore_not_exist(true)
+        .builder();
+    client.clone().send(http_fn).await?.into_data()?;
+    Ok(())
+}
+
+pub async fn rename(client: &OpenApiClient, src_path: &str, dest_path: &str) -> anyhow::Result<()> {
+    let http_fn = ApiStorageMoveRequest::new()
+        .with_src_path(RemotePath::new(src_path)?)
+        .with_dest_path(RemotePath::new(dest_path)?)
+        .builder();
+    client.clone().send(http_fn).await?.into_data()?;
+    Ok(())
+}
+
+/// Joins a remote directory and a relative path with a single `/`.
+pub fn join(dir: &str, name: &str) -> String {
+    let name = name.trim_start_matches('/');
+    if name.is_empty() {
+        return dir.to_string();
+    }
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// Whether `path` is `dir` itself or below it, ignoring a trailing `/` on `dir`.
+pub fn is_within(path: &str, dir: &str) -> bool {
+    let dir = dir.trim_end_matches('/');
+    path == dir
+        || path
+            .strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("/u1", "a.txt"), "/u1/a.txt");
+        assert_eq!(join("/u1/", "/a/b.txt"), "/u1/a/b.txt");
+        assert_eq!(join("/u1", ""), "/u1");
+    }
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within("/u/a", "/u/a"));
+        assert!(is_within("/u/a/b", "/u/a/"));
+        assert!(!is_within("/u/ab", "/u/a"));
+        assert!(is_within("case/mesh.inp", "case"));
+    }
+}
@@ -0,0 +1,556 @@
+use crate::common::client::OpenApiClient;
+use crate::model::file::FileInfo;
+use crate::storage::mirror::{PathFilter, walk_local, walk_remote};
+use crate::storage::{fs, transfer};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// What a [`LocalWatcher`] did with a batch of local changes. Paths are
+/// relative to the watched directory, using `/` as separator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    Uploaded {
+        path: String,
+        bytes: u64,
+    },
+    CreatedDir {
+        path: String,
+    },
+    Removed {
+        path: String,
+    },
+    Renamed {
+        from: String,
+        to: String,
+    },
+    /// The remote file was changed by someone else since it was last synced,
+    /// so the local change was not pushed. `expected` is the last `mod_time`
+    /// seen by the watcher, `None` for a file it has never seen.
+    Conflict {
+        path: String,
+        expected: Option<String>,
+        actual: String,
+    },
+    Failed {
+        path: String,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Upsert(String),
+    Remove(String),
+    Rename(String, String),
+}
+
+impl Action {
+    fn path(&self) -> &str {
+        match self {
+            Action::Upsert(path) | Action::Remove(path) | Action::Rename(path, _) => path,
+        }
+    }
+
+    fn touches(&self, path: &str) -> bool {
+        match self {
+            Action::Upsert(p) | Action::Remove(p) => p == path,
+            Action::Rename(from, to) => from == path || to == path,
+        }
+    }
+}
+
+/// Keeps a remote directory in sync with a local one by pushing local
+/// creates, modifies, renames and deletes as they happen.
+///
+/// Events are collected until none arrived for the debounce interval and then
+/// applied as one batch. Before a remote file is overwritten, moved or removed
+/// its `mod_time` is compared with the one recorded when the watcher last saw
+/// it; a difference is reported as [`SyncEvent::Conflict`] and the change is
+/// skipped.
+#[derive(Debug, Clone)]
+pub struct LocalWatcher {
+    client: OpenApiClient,
+    local_dir: PathBuf,
+    remote_dir: String,
+    debounce: Duration,
+    exclude: Vec<String>,
+}
+
+impl LocalWatcher {
+    pub fn new(client: OpenApiClient, local_dir: impl AsRef<Path>, remote_dir: &str) -> Self {
+        Self {
+            client,
+            local_dir: local_dir.as_ref().to_path_buf(),
+            remote_dir: remote_dir.to_string(),
+            debounce: DEFAULT_DEBOUNCE,
+            exclude: Vec::new(),
+        }
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Ignores paths matching the glob, e.g. editor swap files.
+    pub fn with_exclude(mut self, pattern: String) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Watches until the returned future is dropped, reporting every applied
+    /// change on `on_event`. Only changes made after the call are pushed; use
+    /// [`Mirror`](crate::storage::mirror::Mirror) for the initial upload.
+    pub async fn run(self, mut on_event: impl FnMut(&SyncEvent)) -> anyhow::Result<()> {
+        let local_dir = tokio::fs::canonicalize(&self.local_dir).await?;
+        let filter = PathFilter::new(&[], &self.exclude)?;
+        fs::mkdir(&self.client, &self.remote_dir).await?;
+        let (_, files) = walk_remote(&self.client, &self.remote_dir, &filter).await?;
+        let mut known: HashMap<String, String> = files
+            .into_iter()
+            .map(|(path, file_info)| (path, file_info.mod_time))
+            .collect();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let _ = tx.send(event);
+        })?;
+        watcher.watch(&local_dir, RecursiveMode::Recursive)?;
+
+        while let Some(event) = rx.recv().await {
+            let mut events = vec![event];
+            while let Ok(Some(event)) = tokio::time::timeout(self.debounce, rx.recv()).await {
+                events.push(event);
+            }
+
+            let mut actions = Vec::new();
+            for event in events {
+                match event {
+                    Ok(event) => {
+                        for action in actions_for(&event, &local_dir, &filter) {
+                            push_action(&mut actions, action);
+                        }
+                    }
+                    Err(e) => on_event(&SyncEvent::Failed {
+                        path: String::new(),
+                        error: e.to_string(),
+                    }),
+                }
+            }
+            for action in actions {
+                let path = action.path().to_string();
+                if let Err(e) = self
+                    .apply(action, &local_dir, &filter, &mut known, &mut on_event)
+                    .await
+                {
+                    on_event(&SyncEvent::Failed {
+                        path,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn apply(
+        &self,
+        action: Action,
+        local_dir: &Path,
+        filter: &PathFilter,
+        known: &mut HashMap<String, String>,
+        on_event: &mut impl FnMut(&SyncEvent),
+    ) -> anyhow::Result<()> {
+        match action {
+            Action::Upsert(path) => {
+                let Ok(metadata) = tokio::fs::metadata(local_dir.join(&path)).await else {
+                    // created and removed again within the batch
+                    return Ok(());
+                };
+                if !metadata.is_dir() {
+                    return self.push_file(&path, local_dir, known, on_event).await;
+                }
+                fs::mkdir(&self.client, &fs::join(&self.remote_dir, &path)).await?;
+                on_event(&SyncEvent::CreatedDir { path: path.clone() });
+                // a directory moved into the tree brings its content without events
+                let (dirs, files) = walk_local(&local_dir.join(&path), filter).await?;
+                for dir in dirs {
+                    let dir = format!("{}/{}", path, dir);
+                    fs::mkdir(&self.client, &fs::join(&self.remote_dir, &dir)).await?;
+                    on_event(&SyncEvent::CreatedDir { path: dir });
+                }
+                for (file, _) in files {
+                    let file = format!("{}/{}", path, file);
+                    if let Err(e) = self.push_file(&file, local_dir, known, on_event).await {
+                        on_event(&SyncEvent::Failed {
+                            path: file,
+                            error: e.to_string(),
+                        });
+                    }
+                }
+                Ok(())
+            }
+            Action::Remove(path) => {
+                let remote_path = fs::join(&self.remote_dir, &path);
+                let Some(remote) = fs::stat_if_exists(&self.client, &remote_path).await? else {
+                    forget(known, &path);
+                    return Ok(());
+                };
+                if let Some(conflict) = conflict(known, &path, &remote) {
+                    on_event(&conflict);
+                    return Ok(());
+                }
+                fs::remove(&self.client, &remote_path).await?;
+                forget(known, &path);
+                on_event(&SyncEvent::Removed { path });
+                Ok(())
+            }
+            Action::Rename(from, to) => {
+                let remote_from = fs::join(&self.remote_dir, &from);
+                let Some(remote) = fs::stat_if_exists(&self.client, &remote_from).await? else {
+                    return Box::pin(self.apply(
+                        Action::Upsert(to),
+                        local_dir,
+                        filter,
+                        known,
+                        on_event,
+                    ))
+                    .await;
+                };
+                if let Some(conflict) = conflict(known, &from, &remote) {
+                    on_event(&conflict);
+                    return Ok(());
+                }
+                fs::rename(&self.client, &remote_from, &fs::join(&self.remote_dir, &to)).await?;
+                move_known(known, &from, &to);
+                on_event(&SyncEvent::Renamed { from, to });
+                Ok(())
+            }
+        }
+    }
+
+    async fn push_file(
+        &self,
+        path: &str,
+        local_dir: &Path,
+        known: &mut HashMap<String, String>,
+        on_event: &mut impl FnMut(&SyncEvent),
+    ) -> anyhow::Result<()> {
+        let remote_path = fs::join(&self.remote_dir, path);
+        if let Some(remote) = fs::stat_if_exists(&self.client, &remote_path).await?
+            && let Some(conflict) = conflict(known, path, &remote)
+        {
+            on_event(&conflict);
+            return Ok(());
+        }
+        let bytes = transfer::upload_file(&self.client, local_dir.join(path), &remote_path).await?;
+        let remote = fs::stat(&self.client, &remote_path).await?;
+        known.insert(path.to_string(), remote.mod_time);
+        on_event(&SyncEvent::Uploaded {
+            path: path.to_string(),
+            bytes,
+        });
+        Ok(())
+    }
+}
+
+/// Maps a notify event to actions on paths relative to `root`, dropping
+/// excluded paths and access or metadata-only changes.
+fn actions_for(event: &Event, root: &Path, filter: &PathFilter) -> Vec<Action> {
+    let relative = |path: &PathBuf| relative_path(root, path).filter(|p| !is_excluded(filter, p));
+    let each = |action: fn(String) -> Action| -> Vec<Action> {
+        event
+            .paths
+            .iter()
+            .filter_map(relative)
+            .map(action)
+            .collect()
+    };
+    match event.kind {
+        EventKind::Create(_)
+        | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any | ModifyKind::Other)
+        | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => each(Action::Upsert),
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            each(Action::Remove)
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+            match (relative(&event.paths[0]), relative(&event.paths[1])) {
+                (Some(from), Some(to)) => vec![Action::Rename(from, to)],
+                (None, Some(to)) => vec![Action::Upsert(to)],
+                (Some(from), None) => vec![Action::Remove(from)],
+                (None, None) => Vec::new(),
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(_)) | EventKind::Any | EventKind::Other => event
+            .paths
+            .iter()
+            .filter_map(|path| {
+                let relative = relative(path)?;
+                Some(if path.exists() {
+                    Action::Upsert(relative)
+                } else {
+                    Action::Remove(relative)
+                })
+            })
+            .collect(),
+        EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => Vec::new(),
+    }
+}
+
+/// Appends `action` unless the latest pending action on its path is the same
+/// or it is an upsert below a directory that is already pending an upsert.
+/// A rename replaces the `From`/`To` halves some backends report before it.
+fn push_action(actions: &mut Vec<Action>, action: Action) {
+    match &action {
+        Action::Rename(from, to) => actions.retain(|pending| {
+            *pending != Action::Remove(from.clone()) && *pending != Action::Upsert(to.clone())
+        }),
+        Action::Upsert(path) | Action::Remove(path) => {
+            if actions.iter().rev().find(|pending| pending.touches(path)) == Some(&action) {
+                return;
+            }
+            if matches!(action, Action::Upsert(_))
+                && actions.iter().any(|pending| {
+                    matches!(pending, Action::Upsert(dir) if dir != path && fs::is_within(path, dir))
+                })
+            {
+                return;
+            }
+        }
+    }
+    actions.push(action);
+}
+
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let segments: Vec<String> = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+fn is_excluded(filter: &PathFilter, path: &str) -> bool {
+    path.match_indices('/')
+        .map(|(i, _)| &path[..i])
+        .chain([path])
+        .any(|prefix| !filter.allows_dir(prefix))
+}
+
+fn conflict(known: &HashMap<String, String>, path: &str, remote: &FileInfo) -> Option<SyncEvent> {
+    if remote.is_dir {
+        return None;
+    }
+    let expected = known.get(path);
+    (expected != Some(&remote.mod_time)).then(|| SyncEvent::Conflict {
+        path: path.to_string(),
+        expected: expected.cloned(),
+        actual: remote.mod_time.clone(),
+    })
+}
+
+fn forget(known: &mut HashMap<String, String>, path: &str) {
+    known.retain(|known_path, _| !fs::is_within(known_path, path));
+}
+
+fn move_known(known: &mut HashMap<String, String>, from: &str, to: &str) {
+    let moved: Vec<(String, String)> = known
+        .iter()
+        .filter(|(path, _)| fs::is_within(path, from))
+        .map(|(path, mod_time)| (format!("{}{}", to, &path[from.len()..]), mod_time.clone()))
+        .collect();
+    forget(known, from);
+    known.extend(moved);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, MetadataKind, RemoveKind};
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths.iter().fold(Event::new(kind), |event, path| {
+            event.add_path(PathBuf::from(path))
+        })
+    }
+
+    #[test]
+    fn test_actions_for() -> anyhow::Result<()> {
+        let root = Path::new("/w");
+        let filter = PathFilter::new(&[], &["**/*.swp".to_string(), "tmp".to_string()])?;
+        let actions = |kind, paths: &[&str]| actions_for(&event(kind, paths), root, &filter);
+
+        assert_eq!(
+            actions(EventKind::Create(CreateKind::File), &["/w/a.inp"]),
+            [Action::Upsert("a.inp".to_string())]
+        );
+        assert_eq!(
+            actions(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                &["/w/case/b.inp", "/w/case/.b.inp.swp", "/w/tmp/c.inp"]
+            ),
+            [Action::Upsert("case/b.inp".to_string())]
+        );
+        assert_eq!(
+            actions(EventKind::Remove(RemoveKind::File), &["/w/a.inp"]),
+            [Action::Remove("a.inp".to_string())]
+        );
+        assert_eq!(
+            actions(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/w/a.inp", "/w/b.inp"]
+            ),
+            [Action::Rename("a.inp".to_string(), "b.inp".to_string())]
+        );
+        assert_eq!(
+            actions(
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+                &["/w/tmp/a.inp", "/w/b.inp"]
+            ),
+            [Action::Upsert("b.inp".to_string())]
+        );
+        assert!(
+            actions(
+                EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+                &["/w/a.inp"]
+            )
+            .is_empty()
+        );
+        assert!(actions(EventKind::Create(CreateKind::Folder), &["/w"]).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_push_action() {
+        let upsert = |path: &str| Action::Upsert(path.to_string());
+        let remove = |path: &str| Action::Remove(path.to_string());
+        let mut actions = Vec::new();
+        for action in [
+            upsert("a"),
+            upsert("a"),
+            upsert("a/x"),
+            remove("b"),
+            upsert("c"),
+            Action::Rename("b".to_string(), "c".to_string()),
+            remove("a"),
+            upsert("a"),
+        ] {
+            push_action(&mut actions, action);
+        }
+        assert_eq!(
+            actions,
+            [
+                upsert("a"),
+                Action::Rename("b".to_string(), "c".to_string()),
+                remove("a"),
+                upsert("a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_known_mod_times() {
+        let mut known = HashMap::from([
+            ("d/a".to_string(), "t1".to_string()),
+            ("d/sub/b".to_string(), "t2".to_string()),
+            ("dd".to_string(), "t3".to_string()),
+        ]);
+        move_known(&mut known, "d", "e");
+        assert_eq!(known.get("e/a").map(String::as_str), Some("t1"));
+        assert_eq!(known.get("e/sub/b").map(String::as_str), Some("t2"));
+        assert_eq!(known.get("dd").map(String::as_str), Some("t3"));
+        assert_eq!(known.len(), 3);
+
+        let remote = FileInfo {
+            mod_time: "t9".to_string(),
+            ..Default::default()
+        };
+        assert!(conflict(&known, "e/a", &remote).is_some());
+        assert!(conflict(&known, "new", &remote).is_some());
+        let remote = FileInfo {
+            mod_time: "t1".to_string(),
+            ..Default::default()
+        };
+        assert!(conflict(&known, "e/a", &remote).is_none());
+
+        forget(&mut known, "e");
+        assert_eq!(known.len(), 1);
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_apply() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+
+        let storage = TestStorage::start().await?;
+        let client = &storage.client;
+        let local_dir = storage.root.join("local");
+        tokio::fs::create_dir_all(&local_dir).await?;
+        tokio::fs::write(local_dir.join("a.inp"), "a").await?;
+        tokio::fs::write(local_dir.join("b.inp"), "bb").await?;
+        fs::mkdir(client, "/u1/w").await?;
+
+        let watcher = LocalWatcher::new(client.clone(), &local_dir, "/u1/w");
+        let filter = PathFilter::new(&[], &[])?;
+        let mut known = HashMap::new();
+        let mut events = Vec::new();
+        let mut apply = async |action: Action, known: &mut HashMap<String, String>| {
+            let mut on_event = |event: &SyncEvent| events.push(event.clone());
+            watcher
+                .apply(action, &local_dir, &filter, known, &mut on_event)
+                .await
+        };
+
+        apply(Action::Upsert("a.inp".to_string()), &mut known).await?;
+        apply(Action::Upsert("b.inp".to_string()), &mut known).await?;
+        tokio::fs::rename(local_dir.join("a.inp"), local_dir.join("c.inp")).await?;
+        apply(
+            Action::Rename("a.inp".to_string(), "c.inp".to_string()),
+            &mut known,
+        )
+        .await?;
+        tokio::fs::remove_file(local_dir.join("c.inp")).await?;
+        apply(Action::Remove("c.inp".to_string()), &mut known).await?;
+        // someone else changed b.inp since it was pushed
+        known.insert("b.inp".to_string(), "stale".to_string());
+        tokio::fs::write(local_dir.join("b.inp"), "local").await?;
+        apply(Action::Upsert("b.inp".to_string()), &mut known).await?;
+
+        let remote = fs::stat(client, "/u1/w/b.inp").await?;
+        assert_eq!(
+            events,
+            [
+                SyncEvent::Uploaded {
+                    path: "a.inp".to_string(),
+                    bytes: 1
+                },
+                SyncEvent::Uploaded {
+                    path: "b.inp".to_string(),
+                    bytes: 2
+                },
+                SyncEvent::Renamed {
+                    from: "a.inp".to_string(),
+                    to: "c.inp".to_string()
+                },
+                SyncEvent::Removed {
+                    path: "c.inp".to_string()
+                },
+                SyncEvent::Conflict {
+                    path: "b.inp".to_string(),
+                    expected: Some("stale".to_string()),
+                    actual: remote.mod_time,
+                },
+            ]
+        );
+        assert_eq!(fs::download(client, "/u1/w/b.inp").await?, "bb");
+        assert!(fs::stat_if_exists(client, "/u1/w/c.inp").await?.is_none());
+        Ok(())
+    }
+}
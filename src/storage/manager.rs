@@ -0,0 +1,540 @@
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpStreamBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::storage::fs;
+use crate::storage::range::ByteRange;
+use crate::storage::transfer::PART_SIZE;
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+const DEFAULT_CONCURRENCY: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    Upload,
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferJob {
+    pub id: u64,
+    pub direction: TransferDirection,
+    pub local_path: PathBuf,
+    pub remote_path: String,
+    /// Higher priorities run first, equal priorities in the order they were queued.
+    pub priority: i32,
+    pub size: Option<u64>,
+    /// Bytes confirmed on the destination; a resumed transfer continues from here.
+    pub offset: u64,
+    pub state: TransferState,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    next_id: u64,
+    jobs: Vec<TransferJob>,
+}
+
+impl Journal {
+    fn push(
+        &mut self,
+        direction: TransferDirection,
+        local_path: PathBuf,
+        remote_path: String,
+        priority: i32,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.jobs.push(TransferJob {
+            id,
+            direction,
+            local_path,
+            remote_path,
+            priority,
+            size: None,
+            offset: 0,
+            state: TransferState::Queued,
+            error: None,
+        });
+        id
+    }
+
+    /// Marks the most urgent queued job as running and returns it.
+    fn start_next(&mut self) -> Option<TransferJob> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .filter(|job| job.state == TransferState::Queued)
+            .min_by_key(|job| (-(job.priority as i64), job.id))?;
+        job.state = TransferState::Running;
+        Some(job.clone())
+    }
+
+    fn job_mut(&mut self, id: u64) -> Option<&mut TransferJob> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+}
+
+/// Runs queued uploads and downloads with priorities and bounded concurrency,
+/// recording confirmed offsets in a JSON journal so that transfers
+/// interrupted by a crash or restart resume where they stopped.
+#[derive(Debug, Clone)]
+pub struct TransferManager {
+    client: OpenApiClient,
+    journal_path: PathBuf,
+    concurrency: usize,
+    journal: Arc<Mutex<Journal>>,
+}
+
+impl TransferManager {
+    /// Opens or creates the journal at `journal_path`. Jobs that were running
+    /// when the previous process stopped are queued again.
+    pub async fn open(
+        client: OpenApiClient,
+        journal_path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let journal_path = journal_path.as_ref().to_path_buf();
+        let mut journal: Journal = match tokio::fs::read(&journal_path).await {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Journal::default(),
+            Err(e) => return Err(e.into()),
+        };
+        for job in &mut journal.jobs {
+            if job.state == TransferState::Running {
+                job.state = TransferState::Queued;
+            }
+        }
+        Ok(Self {
+            client,
+            journal_path,
+            concurrency: DEFAULT_CONCURRENCY,
+            journal: Arc::new(Mutex::new(journal)),
+        })
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn enqueue_upload(
+        &self,
+        local_path: impl AsRef<Path>,
+        remote_path: &str,
+        priority: i32,
+    ) -> anyhow::Result<u64> {
+        self.enqueue(
+            TransferDirection::Upload,
+            local_path.as_ref(),
+            remote_path,
+            priority,
+        )
+        .await
+    }
+
+    pub async fn enqueue_download(
+        &self,
+        remote_path: &str,
+        local_path: impl AsRef<Path>,
+        priority: i32,
+    ) -> anyhow::Result<u64> {
+        self.enqueue(
+            TransferDirection::Download,
+            local_path.as_ref(),
+            remote_path,
+            priority,
+        )
+        .await
+    }
+
+    async fn enqueue(
+        &self,
+        direction: TransferDirection,
+        local_path: &Path,
+        remote_path: &str,
+        priority: i32,
+    ) -> anyhow::Result<u64> {
+        let mut journal = self.journal.lock().await;
+        let id = journal.push(
+            direction,
+            local_path.to_path_buf(),
+            remote_path.to_string(),
+            priority,
+        );
+        self.persist(&journal).await?;
+        Ok(id)
+    }
+
+    pub async fn jobs(&self) -> Vec<TransferJob> {
+        self.journal.lock().await.jobs.clone()
+    }
+
+    /// Queues failed jobs again; they resume from their confirmed offset.
+    pub async fn retry_failed(&self) -> anyhow::Result<()> {
+        let mut journal = self.journal.lock().await;
+        for job in &mut journal.jobs {
+            if job.state == TransferState::Failed {
+                job.state = TransferState::Queued;
+                job.error = None;
+            }
+        }
+        self.persist(&journal).await
+    }
+
+    /// Drops completed jobs from the journal.
+    pub async fn remove_completed(&self) -> anyhow::Result<()> {
+        let mut journal = self.journal.lock().await;
+        journal
+            .jobs
+            .retain(|job| job.state != TransferState::Completed);
+        self.persist(&journal).await
+    }
+
+    /// Runs queued jobs until none is left and returns the state of every job.
+    pub async fn run(&self) -> anyhow::Result<Vec<TransferJob>> {
+        futures::future::try_join_all((0..self.concurrency).map(|_| self.worker())).await?;
+        Ok(self.jobs().await)
+    }
+
+    async fn worker(&self) -> anyhow::Result<()> {
+        loop {
+            let job = {
+                let mut journal = self.journal.lock().await;
+                let Some(job) = journal.start_next() else {
+                    return Ok(());
+                };
+                self.persist(&journal).await?;
+                job
+            };
+            let result = match job.direction {
+                TransferDirection::Upload => self.upload(&job).await,
+                TransferDirection::Download => self.download(&job).await,
+            };
+            let mut journal = self.journal.lock().await;
+            if let Some(entry) = journal.job_mut(job.id) {
+                match result {
+                    Ok(()) => entry.state = TransferState::Completed,
+                    Err(e) => {
+                        warn!("transfer {} of {} failed: {}", job.id, job.remote_path, e);
+                        entry.state = TransferState::Failed;
+                        entry.error = Some(e.to_string());
+                    }
+                }
+            }
+            self.persist(&journal).await?;
+        }
+    }
+
+    async fn confirm(&self, id: u64, size: u64, offset: u64) -> anyhow::Result<()> {
+        let mut journal = self.journal.lock().await;
+        if let Some(job) = journal.job_mut(id) {
+            job.size = Some(size);
+            job.offset = offset;
+        }
+        self.persist(&journal).await
+    }
+
+    async fn upload(&self, job: &TransferJob) -> anyhow::Result<()> {
+        let mut file = File::open(&job.local_path).await?;
+        let size = file.metadata().await?.len();
+        let mut offset = job.offset.min(size);
+        if offset > 0 {
+            let remote_size = fs::stat_if_exists(&self.client, &job.remote_path)
+                .await?
+                .map_or(0, |file_info| file_info.size as u64);
+            offset = offset.min(remote_size);
+        }
+        if job.size.is_some_and(|job_size| job_size != size) {
+            offset = 0;
+        }
+
+        file.seek(SeekFrom::Start(offset)).await?;
+        for part in ByteRange::split(size - offset, PART_SIZE) {
+            let mut buffer = vec![0u8; part.length as usize];
+            file.read_exact(&mut buffer).await?;
+            if offset == 0 {
+                fs::upload(&self.client, &job.remote_path, buffer, true).await?;
+            } else {
+                fs::write_at(&self.client, &job.remote_path, offset, Bytes::from(buffer)).await?;
+            }
+            offset += part.length;
+            self.confirm(job.id, size, offset).await?;
+        }
+        if size == 0 {
+            fs::upload(&self.client, &job.remote_path, Vec::new(), true).await?;
+            self.confirm(job.id, size, 0).await?;
+        }
+        Ok(())
+    }
+
+    async fn download(&self, job: &TransferJob) -> anyhow::Result<()> {
+        let file_info = fs::stat(&self.client, &job.remote_path).await?;
+        if file_info.is_dir {
+            bail!("{} is a directory", job.remote_path);
+        }
+        let size = file_info.size as u64;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&job.local_path)
+            .await?;
+        let local_size = file.metadata().await?.len();
+        let mut offset = job.offset.min(local_size).min(size);
+        if job.size.is_some_and(|job_size| job_size != size) {
+            offset = 0;
+        }
+
+        file.set_len(offset).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        for part in ByteRange::split(size - offset, PART_SIZE) {
+            let range = ByteRange::new(offset, part.length);
+            let http_fn = ApiStorageDownloadRequest::new()
+                .with_path(RemotePath::new(&job.remote_path)?)
+                .with_range_start(range.offset as isize)
+                .with_range_end(range.end() as isize - 1)
+                .stream_builder();
+            let response = self.client.clone().send(http_fn).await?;
+            let mut stream = self
+                .client
+                .throttle(response.stream.ok_or_else(|| anyhow!("stream not found"))?);
+            let mut written = 0u64;
+            while let Some(data) = stream.next().await {
+                let data = data?;
+                written += data.len() as u64;
+                if written > range.length {
+                    bail!("received more than {} bytes for {}", range.length, range);
+                }
+                file.write_all(&data).await?;
+            }
+            if written != range.length {
+                bail!(
+                    "received {} of {} bytes for {}",
+                    written,
+                    range.length,
+                    range
+                );
+            }
+            file.sync_data().await?;
+            offset = range.end();
+            self.confirm(job.id, size, offset).await?;
+        }
+        if size == 0 {
+            self.confirm(job.id, size, 0).await?;
+        }
+        Ok(())
+    }
+
+    async fn persist(&self, journal: &Journal) -> anyhow::Result<()> {
+        let temp_path = self.journal_path.with_extension("tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(journal)?).await?;
+        tokio::fs::rename(&temp_path, &self.journal_path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_start_next() {
+        let mut journal = Journal::default();
+        let low = journal.push(TransferDirection::Upload, "a".into(), "/u1/a".into(), 0);
+        let high = journal.push(TransferDirection::Download, "b".into(), "/u1/b".into(), 5);
+        let high_later = journal.push(TransferDirection::Upload, "c".into(), "/u1/c".into(), 5);
+
+        assert_eq!(journal.start_next().map(|job| job.id), Some(high));
+        assert_eq!(journal.start_next().map(|job| job.id), Some(high_later));
+        assert_eq!(journal.start_next().map(|job| job.id), Some(low));
+        assert!(journal.start_next().is_none());
+        assert!(
+            journal
+                .jobs
+                .iter()
+                .all(|job| job.state == TransferState::Running)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_manager_journal() -> anyhow::Result<()> {
+        let journal_path = std::env::temp_dir().join(format!(
+            "openapi-rs-transfer-journal-{}.json",
+            std::process::id()
+        ));
+        let manager = TransferManager::open(OpenApiClient::default(), &journal_path).await?;
+        let id = manager
+            .enqueue_upload("input.dat", "/u1/input.dat", 1)
+            .await?;
+        manager.journal.lock().await.start_next();
+        manager.confirm(id, 100, 40).await?;
+
+        let reopened = TransferManager::open(OpenApiClient::default(), &journal_path).await?;
+        let jobs = reopened.jobs().await;
+        tokio::fs::remove_file(&journal_path).await?;
+
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].state, TransferState::Queued);
+        assert_eq!(jobs[0].offset, 40);
+        assert_eq!(jobs[0].size, Some(100));
+        Ok(())
+    }
+
+    /// Serves `TestStorage` with requests failing while their endpoint and
+    /// query match one of the `(endpoint, needle)` pairs in `fail`, and counts
+    /// requests per endpoint and needle in `hits`.
+    #[cfg(feature = "emulator")]
+    #[derive(Default)]
+    struct Faults {
+        fail: std::sync::Mutex<Vec<(String, String)>>,
+        hits: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[cfg(feature = "emulator")]
+    impl Faults {
+        async fn start(self: &Arc<Self>) -> anyhow::Result<crate::emulator::testing::TestStorage> {
+            use axum::extract::Request;
+            use axum::http::StatusCode;
+            use axum::middleware::{self, Next};
+            use axum::response::IntoResponse;
+
+            let faults = self.clone();
+            crate::emulator::testing::TestStorage::start_with(move |router| {
+                router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                    let faults = faults.clone();
+                    async move {
+                        let endpoint = request.uri().path().to_string();
+                        let query = request.uri().query().unwrap_or_default().to_string();
+                        faults
+                            .hits
+                            .lock()
+                            .unwrap()
+                            .push(format!("{}?{}", endpoint, query));
+                        let fail = faults
+                            .fail
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .any(|(fail, needle)| *fail == endpoint && query.contains(needle));
+                        if fail {
+                            return (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                                .into_response();
+                        }
+                        next.run(request).await
+                    }
+                }))
+            })
+            .await
+        }
+
+        fn set_fail(&self, fail: &[(&str, &str)]) {
+            *self.fail.lock().unwrap() = fail
+                .iter()
+                .map(|(endpoint, needle)| (endpoint.to_string(), needle.to_string()))
+                .collect();
+        }
+
+        fn hits(&self, endpoint: &str, needle: &str) -> usize {
+            let prefix = format!("{}?", endpoint);
+            self.hits
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|hit| hit.starts_with(&prefix) && hit.contains(needle))
+                .count()
+        }
+    }
+
+    #[cfg(feature = "emulator")]
+    fn two_part_data() -> Vec<u8> {
+        (0..PART_SIZE as usize + 4)
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_upload_resumes_at_confirmed_offset() -> anyhow::Result<()> {
+        let faults = Arc::new(Faults::default());
+        let storage = faults.start().await?;
+        let data = two_part_data();
+        let local_path = storage.root.join("input.dat");
+        tokio::fs::write(&local_path, &data).await?;
+        let manager =
+            TransferManager::open(storage.client.clone(), storage.root.join("journal.json"))
+                .await?;
+        manager
+            .enqueue_upload(&local_path, "/u1/input.dat", 0)
+            .await?;
+
+        faults.set_fail(&[("/api/storage/writeAt", "")]);
+        let jobs = manager.run().await?;
+        assert_eq!(jobs[0].state, TransferState::Failed);
+        assert_eq!(jobs[0].offset, PART_SIZE);
+
+        // an unreachable remote fails the job instead of restarting it
+        faults.set_fail(&[("/api/storage/stat", ""), ("/api/storage/lsWithPage", "")]);
+        manager.retry_failed().await?;
+        let jobs = manager.run().await?;
+        assert_eq!(jobs[0].state, TransferState::Failed);
+        assert_eq!(jobs[0].offset, PART_SIZE);
+
+        faults.set_fail(&[]);
+        manager.retry_failed().await?;
+        let jobs = manager.run().await?;
+        assert_eq!(jobs[0].state, TransferState::Completed);
+        assert_eq!(faults.hits("/api/storage/upload/file", ""), 1);
+        assert_eq!(fs::download(&storage.client, "/u1/input.dat").await?, data);
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_download_resumes_at_confirmed_offset() -> anyhow::Result<()> {
+        let faults = Arc::new(Faults::default());
+        let storage = faults.start().await?;
+        let data = two_part_data();
+        fs::upload(&storage.client, "/u1/output.dat", data.clone(), true).await?;
+        let local_path = storage.root.join("output.dat");
+        let manager =
+            TransferManager::open(storage.client.clone(), storage.root.join("journal.json"))
+                .await?;
+        manager
+            .enqueue_download("/u1/output.dat", &local_path, 0)
+            .await?;
+
+        let second_part = format!("Range=bytes%3D{}-", PART_SIZE);
+        faults.set_fail(&[("/api/storage/download", &second_part)]);
+        let jobs = manager.run().await?;
+        assert_eq!(jobs[0].state, TransferState::Failed);
+        assert_eq!(jobs[0].offset, PART_SIZE);
+        assert_eq!(tokio::fs::metadata(&local_path).await?.len(), PART_SIZE);
+
+        faults.set_fail(&[]);
+        manager.retry_failed().await?;
+        let jobs = manager.run().await?;
+        assert_eq!(jobs[0].state, TransferState::Completed);
+        assert_eq!(faults.hits("/api/storage/download", "Range=bytes%3D0-"), 1);
+        assert_eq!(tokio::fs::read(&local_path).await?, data);
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use crate::common::client::OpenApiClient;
+use crate::model::file::FileInfo;
+use crate::storage::progress::ProgressTracker;
+use crate::storage::{fs, transfer};
+use futures::{StreamExt, stream};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MirrorFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MirrorReport {
+    pub transferred: Vec<String>,
+    pub transferred_bytes: u64,
+    pub skipped: Vec<String>,
+    pub failed: Vec<MirrorFailure>,
+}
+
+impl MirrorReport {
+    pub(crate) fn fail(&mut self, path: String, error: anyhow::Error) {
+        self.failed.push(MirrorFailure {
+            path,
+            error: error.to_string(),
+        });
+    }
+
+    pub(crate) fn record(&mut self, path: String, outcome: anyhow::Result<Option<u64>>) {
+        match outcome {
+            Ok(Some(bytes)) => {
+                self.transferred.push(path);
+                self.transferred_bytes += bytes;
+            }
+            Ok(None) => self.skipped.push(path),
+            Err(e) => self.fail(path, e),
+        }
+    }
+}
+
+/// Mirrors a whole directory tree between the local disk and remote storage.
+///
+/// Include and exclude patterns are globs matched against paths relative to
+/// the mirrored root, using `/` as separator. Excluded directories are not
+/// descended into.
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    client: OpenApiClient,
+    concurrency: usize,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    skip_unchanged: bool,
+    progress: Option<ProgressTracker>,
+}
+
+impl Mirror {
+    pub fn new(client: OpenApiClient) -> Self {
+        Self {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            skip_unchanged: false,
+            progress: None,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_include(mut self, pattern: String) -> Self {
+        self.include.push(pattern);
+        self
+    }
+
+    pub fn with_exclude(mut self, pattern: String) -> Self {
+        self.exclude.push(pattern);
+        self
+    }
+
+    /// Skips files whose size matches and whose target copy is not older than the source.
+    pub fn with_skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    /// Reports the whole mirror on `progress` and every file on a child of it.
+    pub fn with_progress(mut self, progress: ProgressTracker) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    fn start_progress(&self, total_bytes: u64) {
+        if let Some(progress) = &self.progress {
+            progress.set_total(total_bytes);
+        }
+    }
+
+    fn skip_progress(&self, bytes: u64) {
+        if let Some(progress) = &self.progress {
+            progress.advance(bytes);
+        }
+    }
+
+    fn finish_progress(&self) {
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+    }
+
+    pub async fn upload_dir(
+        &self,
+        local_dir: impl AsRef<Path>,
+        remote_dir: &str,
+    ) -> anyhow::Result<MirrorReport> {
+        let filter = PathFilter::new(&self.include, &self.exclude)?;
+        let local_dir = local_dir.as_ref();
+        let (dirs, files) = walk_local(local_dir, &filter).await?;
+
+        let mut report = MirrorReport::default();
+        fs::mkdir(&self.client, remote_dir).await?;
+        for dir in dirs {
+            if let Err(e) = fs::mkdir(&self.client, &fs::join(remote_dir, &dir)).await {
+                report.fail(dir, e);
+            }
+        }
+
+        let remote_files: HashMap<String, FileInfo> = if self.skip_unchanged {
+            walk_remote(&self.client, remote_dir, &filter)
+                .await?
+                .1
+                .into_iter()
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        self.start_progress(files.iter().map(|(_, metadata)| metadata.len()).sum());
+        let outcomes: Vec<(String, anyhow::Result<Option<u64>>)> = stream::iter(files)
+            .map(|(path, metadata)| {
+                let remote_file = remote_files.get(&path);
+                async move {
+                    if remote_file.is_some_and(|remote_file| {
+                        remote_file.size as u64 == metadata.len()
+                            && remote_file.modified().map(SystemTime::from)
+                                >= metadata.modified().ok()
+                    }) {
+                        self.skip_progress(metadata.len());
+                        return (path, Ok(None));
+                    }
+                    let tracker = FileTracker::new(self.progress.as_ref(), &path, metadata.len());
+                    let outcome = transfer::upload_file_inspect(
+                        &self.client,
+                        local_dir.join(&path),
+                        &fs::join(remote_dir, &path),
+                        |data| tracker.advance(data.len()),
+                    )
+                    .await;
+                    (path, tracker.done(outcome).map(Some))
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        for (path, outcome) in outcomes {
+            report.record(path, outcome);
+        }
+        self.finish_progress();
+
+        Ok(report)
+    }
+
+    pub async fn download_dir(
+        &self,
+        remote_dir: &str,
+        local_dir: impl AsRef<Path>,
+    ) -> anyhow::Result<MirrorReport> {
+        let filter = PathFilter::new(&self.include, &self.exclude)?;
+        let local_dir = local_dir.as_ref();
+        let (dirs, files) = walk_remote(&self.client, remote_dir, &filter).await?;
+
+        let mut report = MirrorReport::default();
+        tokio::fs::create_dir_all(local_dir).await?;
+        for dir in dirs {
+            if let Err(e) = tokio::fs::create_dir_all(local_dir.join(&dir)).await {
+                report.fail(dir, e.into());
+            }
+        }
+
+        self.start_progress(
+            files
+                .iter()
+                .map(|(_, file_info)| file_info.size as u64)
+                .sum(),
+        );
+        let outcomes: Vec<(String, anyhow::Result<Option<u64>>)> = stream::iter(files)
+            .map(|(path, file_info)| async move {
+                let local_path = local_dir.join(&path);
+                let modified = file_info.modified().map(SystemTime::from);
+                if self.skip_unchanged
+                    && let Ok(metadata) = tokio::fs::metadata(&local_path).await
+                    && metadata.len() == file_info.size as u64
+                    && modified
+                        .is_some_and(|modified| same_second(modified, metadata.modified().ok()))
+                {
+                    self.skip_progress(file_info.size as u64);
+                    return (path, Ok(None));
+                }
+                let tracker =
+                    FileTracker::new(self.progress.as_ref(), &path, file_info.size as u64);
+                let outcome = download_with_mtime(
+                    &self.client,
+                    &fs::join(remote_dir, &path),
+                    local_path,
+                    modified,
+                    &tracker,
+                )
+                .await;
+                (path, tracker.done(outcome).map(Some))
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        for (path, outcome) in outcomes {
+            report.record(path, outcome);
+        }
+        self.finish_progress();
+
+        Ok(report)
+    }
+}
+
+async fn download_with_mtime(
+    client: &OpenApiClient,
+    remote_path: &str,
+    local_path: PathBuf,
+    modified: Option<SystemTime>,
+    tracker: &FileTracker,
+) -> anyhow::Result<u64> {
+    let size = transfer::download_file_inspect(client, remote_path, &local_path, |data| {
+        tracker.advance(data.len())
+    })
+    .await?;
+    if let Some(modified) = modified {
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&local_path)
+            .await?
+            .into_std()
+            .await;
+        file.set_modified(modified)?;
+    }
+    Ok(size)
+}
+
+/// Progress of one file of a mirror, a no-op when the mirror has no tracker.
+pub(crate) struct FileTracker(Option<ProgressTracker>);
+
+impl FileTracker {
+    pub(crate) fn new(progress: Option<&ProgressTracker>, path: &str, size: u64) -> Self {
+        Self(progress.map(|progress| progress.child(path, Some(size))))
+    }
+
+    pub(crate) fn advance(&self, bytes: usize) {
+        if let Some(tracker) = &self.0 {
+            tracker.advance(bytes as u64);
+        }
+    }
+
+    pub(crate) fn done(&self, outcome: anyhow::Result<u64>) -> anyhow::Result<u64> {
+        if let Some(tracker) = &self.0 {
+            if outcome.is_err() {
+                tracker.rewind(tracker.progress().bytes_done);
+            }
+            tracker.finish();
+        }
+        outcome
+    }
+}
+
+fn same_second(a: SystemTime, b: Option<SystemTime>) -> bool {
+    let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).ok();
+    b.is_some_and(|b| secs(a) == secs(b))
+}
+
+pub(crate) struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        let build = |patterns: &[String]| -> anyhow::Result<GlobSet> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+            }
+            Ok(builder.build()?)
+        };
+        Ok(Self {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(build(include)?)
+            },
+            exclude: build(exclude)?,
+        })
+    }
+
+    pub(crate) fn allows_dir(&self, path: &str) -> bool {
+        !self.exclude.is_match(path)
+    }
+
+    pub(crate) fn allows_file(&self, path: &str) -> bool {
+        !self.exclude.is_match(path)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(path))
+    }
+}
+
+fn join_relative(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// Lists the directories (parents first) and files below `root`, relative to it.
+pub(crate) async fn walk_local(
+    root: &Path,
+    filter: &PathFilter,
+) -> anyhow::Result<(Vec<String>, Vec<(String, std::fs::Metadata)>)> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(prefix) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(root.join(&prefix)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = join_relative(&prefix, &entry.file_name().to_string_lossy());
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                if filter.allows_dir(&path) {
+                    dirs.push(path.clone());
+                    pending.push(path);
+                }
+            } else if metadata.is_file() && filter.allows_file(&path) {
+                files.push((path, metadata));
+            }
+        }
+    }
+    dirs.sort();
+    Ok((dirs, files))
+}
+
+/// Lists the directories (parents first) and files below the remote `root`, relative to it.
+pub(crate) async fn walk_remote(
+    client: &OpenApiClient,
+    root: &str,
+    filter: &PathFilter,
+) -> anyhow::Result<(Vec<String>, Vec<(String, FileInfo)>)> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    let mut pending = vec![String::new()];
+    while let Some(prefix) = pending.pop() {
+        for file_info in fs::list_dir(client, &fs::join(root, &prefix)).await? {
+            let path = join_relative(&prefix, &file_info.name);
+            if file_info.is_dir {
+                if filter.allows_dir(&path) {
+                    dirs.push(path.clone());
+                    pending.push(path);
+                }
+            } else if filter.allows_file(&path) {
+                files.push((path, file_info));
+            }
+        }
+    }
+    dirs.sort();
+    Ok((dirs, files))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_filter() -> anyhow::Result<()> {
+        let filter = PathFilter::new(
+            &["**/*.inp".to_string(), "*.py".to_string()],
+            &["scratch".to_string(), "**/*.tmp".to_string()],
+        )?;
+        assert!(filter.allows_file("runner.py"));
+        assert!(!filter.allows_file("case/runner.py"));
+        assert!(filter.allows_file("case/mesh.inp"));
+        assert!(!filter.allows_file("case/log.txt"));
+        assert!(!filter.allows_file("case/mesh.tmp"));
+        assert!(filter.allows_dir("case"));
+        assert!(!filter.allows_dir("scratch"));
+
+        let filter = PathFilter::new(&[], &[])?;
+        assert!(filter.allows_file("any/file"));
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_mirror() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+
+        let storage = TestStorage::start_with(|router| {
+            router.layer(middleware::from_fn(
+                |request: Request, next: Next| async move {
+                    let query = request.uri().query().unwrap_or_default();
+                    if request.uri().path() == "/api/storage/upload/file"
+                        && query.contains("broken")
+                    {
+                        return (StatusCode::SERVICE_UNAVAILABLE, "unavailable").into_response();
+                    }
+                    next.run(request).await
+                },
+            ))
+        })
+        .await?;
+        let client = &storage.client;
+        let local_dir = storage.root.join("local");
+        tokio::fs::create_dir_all(local_dir.join("case/mesh")).await?;
+        tokio::fs::create_dir_all(local_dir.join("scratch")).await?;
+        tokio::fs::write(local_dir.join("run.py"), "run").await?;
+        tokio::fs::write(local_dir.join("case/deck.inp"), "deck").await?;
+        tokio::fs::write(local_dir.join("case/mesh/part.inp"), "part").await?;
+        tokio::fs::write(local_dir.join("case/broken.inp"), "x").await?;
+        tokio::fs::write(local_dir.join("scratch/tmp.inp"), "tmp").await?;
+
+        let sorted = |mut paths: Vec<String>| {
+            paths.sort();
+            paths
+        };
+        let mirror = Mirror::new(client.clone())
+            .with_exclude("scratch".to_string())
+            .with_skip_unchanged(true);
+        let report = mirror.upload_dir(&local_dir, "/u1/job").await?;
+        assert_eq!(
+            sorted(report.transferred),
+            ["case/deck.inp", "case/mesh/part.inp", "run.py"]
+        );
+        assert_eq!(report.transferred_bytes, 11);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].path, "case/broken.inp");
+        assert_eq!(
+            fs::download(client, "/u1/job/case/mesh/part.inp").await?,
+            "part"
+        );
+        assert!(
+            fs::stat_if_exists(client, "/u1/job/scratch")
+                .await?
+                .is_none()
+        );
+
+        let report = mirror.upload_dir(&local_dir, "/u1/job").await?;
+        assert!(report.transferred.is_empty());
+        assert_eq!(report.skipped.len(), 3);
+
+        let copy_dir = storage.root.join("copy");
+        let report = mirror.download_dir("/u1/job", &copy_dir).await?;
+        assert_eq!(report.transferred.len(), 3);
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            tokio::fs::read_to_string(copy_dir.join("case/mesh/part.inp")).await?,
+            "part"
+        );
+        let report = mirror.download_dir("/u1/job", &copy_dir).await?;
+        assert!(report.transferred.is_empty());
+        assert_eq!(report.skipped.len(), 3);
+        Ok(())
+    }
+}
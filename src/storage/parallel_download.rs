@@ -0,0 +1,254 @@
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpStreamBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::storage::fs;
+use crate::storage::progress::ProgressTracker;
+use crate::storage::range::ByteRange;
+use anyhow::{anyhow, bail};
+use futures::{StreamExt, stream};
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tracing::warn;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_PART_SIZE: u64 = 32 * 1024 * 1024;
+const DEFAULT_MAX_RETRIES: usize = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Downloads a remote file as concurrent byte ranges written positionally
+/// into a preallocated local file.
+#[derive(Debug, Clone)]
+pub struct ParallelDownloader {
+    client: OpenApiClient,
+    concurrency: usize,
+    part_size: u64,
+    max_retries: usize,
+    progress: Option<ProgressTracker>,
+}
+
+impl ParallelDownloader {
+    pub fn new(client: OpenApiClient) -> Self {
+        Self {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+            part_size: DEFAULT_PART_SIZE,
+            max_retries: DEFAULT_MAX_RETRIES,
+            progress: None,
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_part_size(mut self, part_size: u64) -> Self {
+        self.part_size = part_size.max(1);
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: ProgressTracker) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Downloads `remote_path` into `local_path` and returns the number of bytes written.
+    pub async fn download(
+        &self,
+        remote_path: &str,
+        local_path: impl AsRef<Path>,
+    ) -> anyhow::Result<u64> {
+        let local_path = local_path.as_ref();
+        let file_info = fs::stat(&self.client, remote_path).await?;
+        if file_info.is_dir {
+            bail!("{} is a directory", remote_path);
+        }
+        let size = file_info.size as u64;
+        if let Some(progress) = &self.progress {
+            progress.set_total(size);
+        }
+
+        let file = File::create(local_path).await?;
+        file.set_len(size).await?;
+        drop(file);
+
+        let failed: Vec<(ByteRange, anyhow::Error)> =
+            stream::iter(ByteRange::split(size, self.part_size))
+                .map(|range| async move {
+                    self.download_range_with_retry(remote_path, local_path, range)
+                        .await
+                        .map_err(|e| (range, e))
+                })
+                .buffer_unordered(self.concurrency)
+                .filter_map(|result| async move { result.err() })
+                .collect()
+                .await;
+        if let Some(progress) = &self.progress {
+            progress.finish();
+        }
+        if let Some((range, e)) = failed.first() {
+            bail!(
+                "download {} failed for {} of {} ranges, first failure at {}: {}",
+                remote_path,
+                failed.len(),
+                size.div_ceil(self.part_size),
+                range,
+                e
+            );
+        }
+
+        Ok(size)
+    }
+
+    async fn download_range_with_retry(
+        &self,
+        remote_path: &str,
+        local_path: &Path,
+        range: ByteRange,
+    ) -> anyhow::Result<()> {
+        let mut attempt = 0;
+        loop {
+            let mut written = 0;
+            let Err(e) = self
+                .download_range(remote_path, local_path, range, &mut written)
+                .await
+            else {
+                return Ok(());
+            };
+            if let Some(progress) = &self.progress {
+                progress.rewind(written);
+            }
+            if attempt == self.max_retries {
+                return Err(e);
+            }
+            attempt += 1;
+            warn!(
+                "download {} {} failed, retry {}/{}: {}",
+                remote_path, range, attempt, self.max_retries, e
+            );
+            tokio::time::sleep(retry_delay(attempt)).await;
+        }
+    }
+
+    async fn download_range(
+        &self,
+        remote_path: &str,
+        local_path: &Path,
+        range: ByteRange,
+        written: &mut u64,
+    ) -> anyhow::Result<()> {
+        let http_fn = ApiStorageDownloadRequest::new()
+            .with_path(RemotePath::new(remote_path)?)
+            .with_range_start(range.offset as isize)
+            .with_range_end(range.end() as isize - 1)
+            .stream_builder();
+        let response = self.client.clone().send(http_fn).await?;
+        let mut stream = self
+            .client
+            .throttle(response.stream.ok_or_else(|| anyhow!("stream not found"))?);
+
+        let mut file = OpenOptions::new().write(true).open(local_path).await?;
+        file.seek(SeekFrom::Start(range.offset)).await?;
+        while let Some(data) = stream.next().await {
+            let data = data?;
+            if *written + data.len() as u64 > range.length {
+                bail!("received more than {} bytes for {}", range.length, range);
+            }
+            file.write_all(&data).await?;
+            *written += data.len() as u64;
+            if let Some(progress) = &self.progress {
+                progress.advance(data.len() as u64);
+            }
+        }
+        if *written != range.length {
+            bail!(
+                "received {} of {} bytes for {}",
+                written,
+                range.length,
+                range
+            );
+        }
+        file.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Exponential backoff before retry `attempt`, capped at `MAX_RETRY_DELAY`.
+fn retry_delay(attempt: usize) -> Duration {
+    let factor = u32::try_from(attempt)
+        .ok()
+        .and_then(|attempt| 1u32.checked_shl(attempt))
+        .unwrap_or(u32::MAX);
+    RETRY_BASE_DELAY.saturating_mul(factor).min(MAX_RETRY_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), Duration::from_millis(400));
+        assert_eq!(retry_delay(3), Duration::from_millis(1600));
+        assert_eq!(retry_delay(20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(64), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(usize::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_download_retries_a_failed_range() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::extract::Request;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // the first request for bytes 4-7 ends after two bytes
+        let failures = Arc::new(AtomicUsize::new(0));
+        let failures_ref = failures.clone();
+        let storage = TestStorage::start_with(move |router| {
+            router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                let failures = failures_ref.clone();
+                async move {
+                    let query = request.uri().query().unwrap_or_default();
+                    if query.contains("Range=bytes%3D4-7")
+                        && failures.fetch_add(1, Ordering::Relaxed) == 0
+                    {
+                        return "45".into_response();
+                    }
+                    next.run(request).await
+                }
+            }))
+        })
+        .await?;
+        let content = b"0123456789".to_vec();
+        fs::upload(&storage.client, "/u1/a.bin", content.clone(), false).await?;
+
+        let progress = ProgressTracker::new("a.bin", None);
+        let local_path = storage.root.join("a.bin");
+        let size = ParallelDownloader::new(storage.client.clone())
+            .with_part_size(4)
+            .with_concurrency(2)
+            .with_progress(progress.clone())
+            .download("/u1/a.bin", &local_path)
+            .await?;
+
+        assert_eq!(size, 10);
+        assert_eq!(tokio::fs::read(&local_path).await?, content);
+        assert_eq!(failures.load(Ordering::Relaxed), 2);
+        assert_eq!(progress.progress().bytes_done, 10);
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use crate::common::define::BytesStream;
+use futures::StreamExt;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+const UNKNOWN_TOTAL: u64 = u64::MAX;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Progress {
+    pub bytes_done: u64,
+    pub total_bytes: Option<u64>,
+    pub elapsed: Duration,
+    pub finished: bool,
+}
+
+impl Progress {
+    /// Average throughput since the transfer started, in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.bytes_done as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let remaining = self.total_bytes?.saturating_sub(self.bytes_done);
+        if remaining == 0 {
+            return Some(Duration::ZERO);
+        }
+        let throughput = self.throughput();
+        (throughput > 0.0).then(|| Duration::from_secs_f64(remaining as f64 / throughput))
+    }
+
+    pub fn fraction(&self) -> Option<f64> {
+        self.total_bytes.map(|total| match total {
+            0 => 1.0,
+            total => self.bytes_done as f64 / total as f64,
+        })
+    }
+}
+
+/// Receives progress updates for named transfers and batches.
+pub trait ProgressObserver: Send + Sync {
+    fn on_progress(&self, name: &str, progress: &Progress);
+}
+
+impl<F> ProgressObserver for F
+where
+    F: Fn(&str, &Progress) + Send + Sync,
+{
+    fn on_progress(&self, name: &str, progress: &Progress) {
+        self(name, progress)
+    }
+}
+
+/// Counts the bytes of one transfer or of a batch of transfers. Progress
+/// recorded on a child tracker is also recorded on its parent.
+#[derive(Clone)]
+pub struct ProgressTracker {
+    inner: Arc<TrackerInner>,
+}
+
+struct TrackerInner {
+    name: String,
+    started: Instant,
+    bytes_done: AtomicU64,
+    total_bytes: AtomicU64,
+    observers: Mutex<Vec<Arc<dyn ProgressObserver>>>,
+    sender: watch::Sender<Progress>,
+    parent: Option<ProgressTracker>,
+}
+
+impl ProgressTracker {
+    pub fn new(name: &str, total_bytes: Option<u64>) -> Self {
+        Self::build(name, total_bytes, Vec::new(), None)
+    }
+
+    fn build(
+        name: &str,
+        total_bytes: Option<u64>,
+        observers: Vec<Arc<dyn ProgressObserver>>,
+        parent: Option<ProgressTracker>,
+    ) -> Self {
+        let (sender, _) = watch::channel(Progress {
+            total_bytes,
+            ..Default::default()
+        });
+        Self {
+            inner: Arc::new(TrackerInner {
+                name: name.to_string(),
+                started: Instant::now(),
+                bytes_done: AtomicU64::new(0),
+                total_bytes: AtomicU64::new(total_bytes.unwrap_or(UNKNOWN_TOTAL)),
+                observers: Mutex::new(observers),
+                sender,
+                parent,
+            }),
+        }
+    }
+
+    /// Attaches an observer; children created afterwards share it.
+    pub fn with_observer(self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.add_observer(observer);
+        self
+    }
+
+    /// Attaches an observer to a tracker that may already be in use.
+    pub fn add_observer(&self, observer: Arc<dyn ProgressObserver>) {
+        self.inner.observers.lock().unwrap().push(observer);
+    }
+
+    /// Creates a tracker for one transfer of this batch, sharing its observer.
+    pub fn child(&self, name: &str, total_bytes: Option<u64>) -> ProgressTracker {
+        Self::build(
+            name,
+            total_bytes,
+            self.inner.observers.lock().unwrap().clone(),
+            Some(self.clone()),
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    pub fn set_total(&self, total_bytes: u64) {
+        self.inner.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.notify(false);
+    }
+
+    pub fn advance(&self, bytes: u64) {
+        self.inner.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.notify(false);
+        if let Some(parent) = &self.inner.parent {
+            parent.advance(bytes);
+        }
+    }
+
+    /// Takes back bytes that have to be transferred again after a failed attempt.
+    pub fn rewind(&self, bytes: u64) {
+        self.inner.bytes_done.fetch_sub(bytes, Ordering::Relaxed);
+        self.notify(false);
+        if let Some(parent) = &self.inner.parent {
+            parent.rewind(bytes);
+        }
+    }
+
+    pub fn finish(&self) {
+        self.notify(true);
+    }
+
+    pub fn progress(&self) -> Progress {
+        let total_bytes = self.inner.total_bytes.load(Ordering::Relaxed);
+        Progress {
+            bytes_done: self.inner.bytes_done.load(Ordering::Relaxed),
+            total_bytes: (total_bytes != UNKNOWN_TOTAL).then_some(total_bytes),
+            elapsed: self.inner.started.elapsed(),
+            finished: false,
+        }
+    }
+
+    /// Returns a receiver that always holds the latest progress of this tracker.
+    pub fn subscribe(&self) -> watch::Receiver<Progress> {
+        self.inner.sender.subscribe()
+    }
+
+    fn notify(&self, finished: bool) {
+        let progress = Progress {
+            finished,
+            ..self.progress()
+        };
+        let observers = self.inner.observers.lock().unwrap().clone();
+        for observer in observers {
+            observer.on_progress(&self.inner.name, &progress);
+        }
+        self.inner.sender.send_replace(progress);
+    }
+}
+
+impl fmt::Debug for ProgressTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressTracker")
+            .field("name", &self.inner.name)
+            .field("progress", &self.progress())
+            .finish()
+    }
+}
+
+/// Advances `tracker` with every chunk read from `stream`.
+pub fn track_stream(stream: BytesStream, tracker: ProgressTracker) -> BytesStream {
+    Box::pin(stream.inspect(move |data| {
+        if let Ok(data) = data {
+            tracker.advance(data.len() as u64);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress {
+            bytes_done: 25,
+            total_bytes: Some(100),
+            elapsed: Duration::from_secs(5),
+            finished: false,
+        };
+        assert_eq!(progress.throughput(), 5.0);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(15)));
+        assert_eq!(progress.fraction(), Some(0.25));
+        assert_eq!(Progress::default().eta(), None);
+    }
+
+    #[test]
+    fn test_progress_tracker() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observer = {
+            let events = events.clone();
+            move |name: &str, progress: &Progress| {
+                events
+                    .lock()
+                    .unwrap()
+                    .push((name.to_string(), progress.bytes_done));
+            }
+        };
+        let batch = ProgressTracker::new("batch", Some(30)).with_observer(Arc::new(observer));
+        let receiver = batch.subscribe();
+        let a = batch.child("a", Some(10));
+        let b = batch.child("b", Some(20));
+        a.advance(10);
+        b.advance(15);
+        b.rewind(5);
+        b.advance(10);
+        b.finish();
+
+        assert_eq!(a.progress().bytes_done, 10);
+        assert_eq!(b.progress().bytes_done, 20);
+        assert_eq!(batch.progress().bytes_done, 30);
+        assert_eq!(receiver.borrow().bytes_done, 30);
+        assert!(events.lock().unwrap().contains(&("a".to_string(), 10)));
+        assert_eq!(events.lock().unwrap().last(), Some(&("b".to_string(), 20)));
+    }
+
+    #[test]
+    fn test_observer_added_mid_transfer() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let tracker = ProgressTracker::new("upload", Some(10));
+        let receiver = tracker.subscribe();
+        tracker.advance(4);
+        let tracker = tracker.with_observer(Arc::new({
+            let events = events.clone();
+            move |_: &str, progress: &Progress| events.lock().unwrap().push(progress.bytes_done)
+        }));
+        tracker.advance(6);
+
+        assert_eq!(tracker.progress().bytes_done, 10);
+        assert_eq!(receiver.borrow().bytes_done, 10);
+        assert_eq!(*events.lock().unwrap(), [10]);
+    }
+
+    #[tokio::test]
+    async fn test_track_stream() {
+        let tracker = ProgressTracker::new("download", None);
+        let chunks: Vec<std::io::Result<Bytes>> = vec![
+            Ok(Bytes::from_static(b"abc")),
+            Ok(Bytes::from_static(b"de")),
+        ];
+        let stream = track_stream(Box::pin(futures::stream::iter(chunks)), tracker.clone());
+        assert_eq!(stream.count().await, 2);
+        assert_eq!(tracker.progress().bytes_done, 5);
+        assert_eq!(tracker.progress().total_bytes, None);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A half-open byte range `[offset, offset + length)` of a file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    pub fn new(offset: u64, length: u64) -> Self {
+        Self { offset, length }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+
+    /// Splits `[0, total)` into consecutive ranges of at most `part_size` bytes.
+    pub fn split(total: u64, part_size: u64) -> Vec<ByteRange> {
+        let part_size = part_size.max(1);
+        (0..total)
+            .step_by(part_size as usize)
+            .map(|offset| ByteRange::new(offset, part_size.min(total - offset)))
+            .collect()
+    }
+
+    /// Parses a single-range HTTP `Range` header value, `bytes=<first>-[<last>]`
+    /// or `bytes=-<suffix length>`, against a file of `size` bytes. The range
+    /// is clipped to the file and fails when nothing of it is satisfiable.
+    pub fn from_http_range(range: &str, size: u64) -> anyhow::Result<ByteRange> {
+        let Some((first, last)) = range
+            .trim()
+            .strip_prefix("bytes=")
+            .and_then(|range| range.split_once('-'))
+        else {
+            bail!("invalid range {:?}", range);
+        };
+        let (first, last) = (first.trim(), last.trim());
+        let (offset, end) = if first.is_empty() {
+            let suffix: u64 = last.parse()?;
+            (size.saturating_sub(suffix), size)
+        } else {
+            let offset: u64 = first.parse()?;
+            let end = match last {
+                "" => size,
+                last => last.parse::<u64>()?.saturating_add(1).min(size),
+            };
+            (offset, end)
+        };
+        if offset >= end {
+            bail!("range {:?} is not satisfiable for {} bytes", range, size);
+        }
+        Ok(ByteRange::new(offset, end - offset))
+    }
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}, {})", self.offset, self.end())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_range_split() {
+        assert!(ByteRange::split(0, 4).is_empty());
+        assert_eq!(ByteRange::split(3, 4), vec![ByteRange::new(0, 3)]);
+        assert_eq!(
+            ByteRange::split(10, 4),
+            vec![
+                ByteRange::new(0, 4),
+                ByteRange::new(4, 4),
+                ByteRange::new(8, 2)
+            ]
+        );
+        assert_eq!(
+            ByteRange::split(8, 4),
+            vec![ByteRange::new(0, 4), ByteRange::new(4, 4)]
+        );
+    }
+
+    #[test]
+    fn test_byte_range_from_http_range() {
+        let parse = |range: &str| ByteRange::from_http_range(range, 100).ok();
+        assert_eq!(parse("bytes=0-9"), Some(ByteRange::new(0, 10)));
+        assert_eq!(parse("bytes=90-"), Some(ByteRange::new(90, 10)));
+        assert_eq!(parse("bytes=90-200"), Some(ByteRange::new(90, 10)));
+        assert_eq!(parse("bytes=-30"), Some(ByteRange::new(70, 30)));
+        assert_eq!(parse("bytes=-300"), Some(ByteRange::new(0, 100)));
+        assert_eq!(parse("bytes=100-"), None);
+        assert_eq!(parse("bytes=5-1"), None);
+        assert_eq!(parse("bytes=0-1,5-9"), None);
+        assert_eq!(parse("items=0-9"), None);
+    }
+}
@@ -0,0 +1,402 @@
+use crate::common::client::OpenApiClient;
+use crate::common::remote_path::RemotePath;
+use crate::model::file::FileInfo;
+use crate::storage::block_cache::BlockCache;
+use crate::storage::fs;
+use anyhow::bail;
+use bytes::Bytes;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+const DEFAULT_READ_SIZE: u64 = 1024 * 1024;
+const DEFAULT_WRITE_SIZE: usize = 4 * 1024 * 1024;
+
+/// A remote file usable with tokio's `AsyncRead`, `AsyncSeek` and `AsyncWrite`.
+///
+/// Reads are served by `readAt` in chunks of `read_size` bytes and writes are
+/// buffered and sent with `writeAt`. Buffered writes are only sent on
+/// `flush`/`shutdown`, a seek away from the write position, a read, or when
+/// the buffer is full, so flush before dropping the file.
+///
+/// With [`RemoteFile::with_block_cache`] reads go through a shared
+/// [`BlockCache`] in whole blocks instead.
+#[derive(derive_more::Debug)]
+pub struct RemoteFile {
+    client: OpenApiClient,
+    path: String,
+    size: u64,
+    mod_time: String,
+    position: u64,
+    read_size: u64,
+    block_cache: Option<BlockCache>,
+    read_offset: u64,
+    #[debug(skip)]
+    read_buffer: Bytes,
+    #[debug(skip)]
+    pending_read: Option<BoxFuture<'static, (u64, io::Result<Bytes>)>>,
+    write_size: usize,
+    write_offset: u64,
+    #[debug(skip)]
+    write_buffer: Vec<u8>,
+    #[debug(skip)]
+    pending_write: Option<BoxFuture<'static, io::Result<()>>>,
+    /// The data of `pending_write`, put back into the buffer if it fails.
+    #[debug(skip)]
+    flushing: Bytes,
+}
+
+impl RemoteFile {
+    /// Opens an existing remote file, taking its size from `stat`.
+    pub async fn open(client: &OpenApiClient, path: &str) -> anyhow::Result<Self> {
+        let path = RemotePath::new(path)?;
+        let path = path.as_str();
+        let file_info = fs::stat(client, path).await?;
+        if file_info.is_dir {
+            bail!("{} is a directory", path);
+        }
+        Ok(Self::new(
+            client,
+            path,
+            file_info.size as u64,
+            file_info.mod_time,
+        ))
+    }
+
+    /// Creates an empty remote file, replacing any existing one.
+    pub async fn create(client: &OpenApiClient, path: &str) -> anyhow::Result<Self> {
+        let path = RemotePath::new(path)?;
+        let path = path.as_str();
+        fs::upload(client, path, Vec::new(), true).await?;
+        Ok(Self::new(client, path, 0, String::new()))
+    }
+
+    fn new(client: &OpenApiClient, path: &str, size: u64, mod_time: String) -> Self {
+        Self {
+            client: client.clone(),
+            path: path.to_string(),
+            size,
+            mod_time,
+            position: 0,
+            read_size: DEFAULT_READ_SIZE,
+            block_cache: None,
+            read_offset: 0,
+            read_buffer: Bytes::new(),
+            pending_read: None,
+            write_size: DEFAULT_WRITE_SIZE,
+            write_offset: 0,
+            write_buffer: Vec::new(),
+            pending_write: None,
+            flushing: Bytes::new(),
+        }
+    }
+
+    pub fn with_read_size(mut self, read_size: u64) -> Self {
+        self.read_size = read_size.max(1);
+        self
+    }
+
+    /// Serves reads from `block_cache`. Register the cache as a request observer
+    /// on the client so that writes through this file invalidate it.
+    pub fn with_block_cache(mut self, block_cache: BlockCache) -> Self {
+        self.block_cache = Some(block_cache);
+        self
+    }
+
+    pub fn with_write_size(mut self, write_size: usize) -> Self {
+        self.write_size = write_size.max(1);
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Size of the file including buffered writes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn buffered(&self) -> Option<&[u8]> {
+        let start = self.position.checked_sub(self.read_offset)? as usize;
+        (start < self.read_buffer.len()).then(|| &self.read_buffer[start..])
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.pending_write.is_none() {
+            if self.write_buffer.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            let client = self.client.clone();
+            let path = self.path.clone();
+            let offset = self.write_offset;
+            let data = Bytes::from(std::mem::take(&mut self.write_buffer));
+            self.flushing = data.clone();
+            self.pending_write = Some(
+                async move {
+                    fs::write_at(&client, &path, offset, data)
+                        .await
+                        .map_err(io::Error::other)
+                }
+                .boxed(),
+            );
+        }
+        let result = ready!(self.pending_write.as_mut().unwrap().poll_unpin(cx));
+        self.pending_write = None;
+        let flushed = std::mem::take(&mut self.flushing);
+        if result.is_err() {
+            // keep the data so that the next flush retries it
+            self.write_buffer = flushed.to_vec();
+        }
+        Poll::Ready(result)
+    }
+}
+
+fn resolve_seek(position: u64, size: u64, seek: SeekFrom) -> io::Result<u64> {
+    let target = match seek {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::End(delta) => size.checked_add_signed(delta),
+        SeekFrom::Current(delta) => position.checked_add_signed(delta),
+    };
+    target.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+impl AsyncRead for RemoteFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buffer(cx))?;
+        loop {
+            if let Some(data) = this.buffered() {
+                let n = data.len().min(buf.remaining());
+                buf.put_slice(&data[..n]);
+                this.position += n as u64;
+                return Poll::Ready(Ok(()));
+            }
+            if this.position >= this.size {
+                return Poll::Ready(Ok(()));
+            }
+            if this.pending_read.is_none() {
+                let client = this.client.clone();
+                let path = this.path.clone();
+                this.pending_read = Some(match this.block_cache.clone() {
+                    Some(block_cache) => {
+                        let index = this.position / block_cache.block_size();
+                        let file_info = FileInfo {
+                            size: this.size as isize,
+                            mod_time: this.mod_time.clone(),
+                            ..Default::default()
+                        };
+                        async move {
+                            let data = block_cache
+                                .read_block(&client, &path, &file_info, index)
+                                .await
+                                .map_err(io::Error::other);
+                            (index * block_cache.block_size(), data)
+                        }
+                        .boxed()
+                    }
+                    None => {
+                        let offset = this.position;
+                        let length = this.read_size.min(this.size - offset);
+                        async move {
+                            let data = fs::read_at(&client, &path, offset, length)
+                                .await
+                                .map_err(io::Error::other);
+                            (offset, data)
+                        }
+                        .boxed()
+                    }
+                });
+            }
+            let (offset, data) = ready!(this.pending_read.as_mut().unwrap().poll_unpin(cx));
+            this.pending_read = None;
+            let data = data?;
+            if data.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            this.read_offset = offset;
+            this.read_buffer = data;
+            if this.buffered().is_none() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "cached block ends before the read position",
+                )));
+            }
+        }
+    }
+}
+
+impl AsyncSeek for RemoteFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        this.position = resolve_seek(this.position, this.size, position)?;
+        // A read started before the seek would fill the buffer at the old position.
+        this.pending_read = None;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.position))
+    }
+}
+
+impl AsyncWrite for RemoteFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pending_write.is_some()
+            || (!this.write_buffer.is_empty()
+                && (this.position != this.write_offset + this.write_buffer.len() as u64
+                    || this.write_buffer.len() >= this.write_size))
+        {
+            ready!(this.poll_write_buffer(cx))?;
+        }
+        if this.write_buffer.is_empty() {
+            this.write_offset = this.position;
+        }
+        let n = buf.len().min(this.write_size - this.write_buffer.len());
+        this.write_buffer.extend_from_slice(&buf[..n]);
+        this.position += n as u64;
+        this.size = this.size.max(this.position);
+        this.read_buffer = Bytes::new();
+        this.pending_read = None;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_write_buffer(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_write_buffer(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_seek() {
+        assert_eq!(resolve_seek(5, 100, SeekFrom::Start(10)).unwrap(), 10);
+        assert_eq!(resolve_seek(5, 100, SeekFrom::End(-10)).unwrap(), 90);
+        assert_eq!(resolve_seek(5, 100, SeekFrom::End(10)).unwrap(), 110);
+        assert_eq!(resolve_seek(5, 100, SeekFrom::Current(-5)).unwrap(), 0);
+        assert!(resolve_seek(5, 100, SeekFrom::Current(-6)).is_err());
+        assert!(resolve_seek(5, 100, SeekFrom::End(-101)).is_err());
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_seek_during_read() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let storage = TestStorage::start().await?;
+        fs::upload(
+            &storage.client,
+            "/u1/data.bin",
+            b"0123456789abcdef".to_vec(),
+            true,
+        )
+        .await?;
+        let mut file = RemoteFile::open(&storage.client, "/u1/data.bin")
+            .await?
+            .with_read_size(4);
+
+        let mut buf = [0u8; 4];
+        assert!(futures::poll!(Box::pin(file.read(&mut buf))).is_pending());
+        file.seek(SeekFrom::Start(8)).await?;
+        file.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"89ab");
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_write() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+        let storage = TestStorage::start().await?;
+        let client = &storage.client;
+        let mut file = RemoteFile::create(client, "/u1/out.bin").await?;
+
+        file.write_all(b"hello").await?;
+        file.flush().await?;
+        assert_eq!(fs::download(client, "/u1/out.bin").await?, "hello");
+
+        // a write away from the buffered range sends the buffer first
+        file.write_all(b" world").await?;
+        file.seek(SeekFrom::Start(14)).await?;
+        assert_eq!(fs::download(client, "/u1/out.bin").await?, "hello");
+        file.write_all(b"!").await?;
+        assert_eq!(fs::download(client, "/u1/out.bin").await?, "hello world");
+        assert_eq!(file.size(), 15);
+
+        // a read sends buffered writes before reading
+        file.seek(SeekFrom::Start(0)).await?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await?;
+        assert_eq!(data, b"hello world\0\0\0!");
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_flush_retries_failed_write() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use tokio::io::AsyncWriteExt;
+
+        let fail = Arc::new(AtomicBool::new(false));
+        let storage = TestStorage::start_with({
+            let fail = fail.clone();
+            move |router| {
+                router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                    let fail = fail.clone();
+                    async move {
+                        if fail.load(Ordering::Relaxed)
+                            && request.uri().path() == "/api/storage/writeAt"
+                        {
+                            return (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                                .into_response();
+                        }
+                        next.run(request).await
+                    }
+                }))
+            }
+        })
+        .await?;
+        let client = &storage.client;
+        let mut file = RemoteFile::create(client, "/u1/out.bin").await?;
+
+        fail.store(true, Ordering::Relaxed);
+        file.write_all(b"data").await?;
+        assert!(file.flush().await.is_err());
+        fail.store(false, Ordering::Relaxed);
+        file.flush().await?;
+        assert_eq!(fs::download(client, "/u1/out.bin").await?, "data");
+        Ok(())
+    }
+}
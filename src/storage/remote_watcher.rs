@@ -0,0 +1,210 @@
+use crate::model::file::FileInfo;
+use crate::storage::walk::Walker;
+use futures::{Stream, TryStreamExt, stream};
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+use std::time::Duration;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+
+pub type RemoteEventStream = Pin<Box<dyn Stream<Item = anyhow::Result<RemoteEvent>> + Send>>;
+
+/// A change between two snapshots, with the full remote path of the entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteEvent {
+    Created {
+        path: String,
+        file_info: FileInfo,
+    },
+    /// A file whose size or `mod_time` changed.
+    Modified {
+        path: String,
+        file_info: FileInfo,
+    },
+    /// Carries the last `FileInfo` seen before the entry disappeared.
+    Deleted {
+        path: String,
+        file_info: FileInfo,
+    },
+}
+
+impl RemoteEvent {
+    pub fn path(&self) -> &str {
+        match self {
+            RemoteEvent::Created { path, .. }
+            | RemoteEvent::Modified { path, .. }
+            | RemoteEvent::Deleted { path, .. } => path,
+        }
+    }
+}
+
+type Snapshot = BTreeMap<String, FileInfo>;
+
+/// Polls a remote tree and reports what changed since the previous poll.
+///
+/// Every poll lists the tree with the given [`Walker`], so its depth and
+/// filters decide what is watched, and diffs the listing against the previous
+/// snapshot. A poll that fails is yielded as an error and the previous
+/// snapshot is kept, so no spurious deletes are reported.
+#[derive(Debug, Clone)]
+pub struct RemoteWatcher {
+    walker: Walker,
+    interval: Duration,
+    emit_existing: bool,
+}
+
+impl RemoteWatcher {
+    pub fn new(walker: Walker) -> Self {
+        Self {
+            walker,
+            interval: DEFAULT_INTERVAL,
+            emit_existing: false,
+        }
+    }
+
+    /// Time between the end of one poll and the start of the next.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Reports every entry of the first snapshot as created.
+    pub fn with_emit_existing(mut self, emit_existing: bool) -> Self {
+        self.emit_existing = emit_existing;
+        self
+    }
+
+    async fn snapshot(&self) -> anyhow::Result<Snapshot> {
+        self.walker.clone().into_stream().try_collect().await
+    }
+
+    /// Polls until the stream is dropped.
+    pub fn into_stream(self) -> RemoteEventStream {
+        let state = WatchState {
+            watcher: self,
+            previous: None,
+            ready: VecDeque::new(),
+            polled: false,
+        };
+        Box::pin(stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(event) = state.ready.pop_front() {
+                    return Some((Ok(event), state));
+                }
+                if state.polled {
+                    tokio::time::sleep(state.watcher.interval).await;
+                }
+                state.polled = true;
+                let snapshot = match state.watcher.snapshot().await {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => return Some((Err(e), state)),
+                };
+                match &state.previous {
+                    Some(previous) => state.ready.extend(diff_snapshots(previous, &snapshot)),
+                    None if state.watcher.emit_existing => state
+                        .ready
+                        .extend(diff_snapshots(&Snapshot::new(), &snapshot)),
+                    None => {}
+                }
+                state.previous = Some(snapshot);
+            }
+        }))
+    }
+}
+
+struct WatchState {
+    watcher: RemoteWatcher,
+    previous: Option<Snapshot>,
+    ready: VecDeque<RemoteEvent>,
+    polled: bool,
+}
+
+/// Creates and modifications in path order, then deletes children first.
+/// Directories are only reported when they appear or disappear, since their
+/// `mod_time` changes with every entry below them.
+fn diff_snapshots(previous: &Snapshot, next: &Snapshot) -> Vec<RemoteEvent> {
+    let mut events = Vec::new();
+    for (path, file_info) in next {
+        match previous.get(path) {
+            None => events.push(RemoteEvent::Created {
+                path: path.clone(),
+                file_info: file_info.clone(),
+            }),
+            Some(old)
+                if old.is_dir != file_info.is_dir
+                    || (!file_info.is_dir
+                        && (old.size != file_info.size || old.mod_time != file_info.mod_time)) =>
+            {
+                events.push(RemoteEvent::Modified {
+                    path: path.clone(),
+                    file_info: file_info.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, file_info) in previous.iter().rev() {
+        if !next.contains_key(path) {
+            events.push(RemoteEvent::Deleted {
+                path: path.clone(),
+                file_info: file_info.clone(),
+            });
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, size: isize, mod_time: &str, is_dir: bool) -> (String, FileInfo) {
+        let file_info = FileInfo {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            size,
+            mod_time: mod_time.to_string(),
+            is_dir,
+            ..Default::default()
+        };
+        (path.to_string(), file_info)
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let previous = Snapshot::from([
+            entry("/u1/out", 0, "t1", true),
+            entry("/u1/out/a.log", 10, "t1", false),
+            entry("/u1/out/b.log", 10, "t1", false),
+            entry("/u1/old", 0, "t1", true),
+            entry("/u1/old/c.log", 10, "t1", false),
+        ]);
+        let next = Snapshot::from([
+            entry("/u1/out", 0, "t2", true),
+            entry("/u1/out/a.log", 10, "t1", false),
+            entry("/u1/out/b.log", 20, "t2", false),
+            entry("/u1/out/result.dat", 5, "t2", false),
+        ]);
+        let events = diff_snapshots(&previous, &next);
+        let events: Vec<(&str, &str)> = events
+            .iter()
+            .map(|event| {
+                let kind = match event {
+                    RemoteEvent::Created { .. } => "created",
+                    RemoteEvent::Modified { .. } => "modified",
+                    RemoteEvent::Deleted { .. } => "deleted",
+                };
+                (kind, event.path())
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                ("modified", "/u1/out/b.log"),
+                ("created", "/u1/out/result.dat"),
+                ("deleted", "/u1/old/c.log"),
+                ("deleted", "/u1/old"),
+            ]
+        );
+        assert!(diff_snapshots(&next, &next).is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpStreamBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::storage::fs;
+use crate::storage::range::ByteRange;
+use anyhow::{anyhow, bail};
+use bytes::Bytes;
+use futures::StreamExt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::warn;
+
+/// Largest body sent in a single upload or `writeAt` request.
+pub const PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Uploads a local file, replacing the remote file, and returns its size.
+///
+/// The first part is sent with the upload API so the remote file is created
+/// or replaced, the remaining parts are appended with `writeAt`.
+pub async fn upload_file(
+    client: &OpenApiClient,
+    local_path: impl AsRef<Path>,
+    remote_path: &str,
+) -> anyhow::Result<u64> {
+    upload_file_inspect(client, local_path, remote_path, |_| {}).await
+}
+
+/// Like [`upload_file`], calling `inspect` with every part in file order once it is sent.
+pub async fn upload_file_inspect(
+    client: &OpenApiClient,
+    local_path: impl AsRef<Path>,
+    remote_path: &str,
+    mut inspect: impl FnMut(&[u8]),
+) -> anyhow::Result<u64> {
+    let mut file = File::open(local_path.as_ref()).await?;
+    let size = file.metadata().await?.len();
+
+    let mut buffer = vec![0u8; size.min(PART_SIZE) as usize];
+    file.read_exact(&mut buffer).await?;
+    fs::upload(client, remote_path, buffer.clone(), true).await?;
+    inspect(&buffer);
+
+    for range in ByteRange::split(size, PART_SIZE).into_iter().skip(1) {
+        let part = &mut buffer[..range.length as usize];
+        file.read_exact(part).await?;
+        fs::write_at(
+            client,
+            remote_path,
+            range.offset,
+            Bytes::copy_from_slice(part),
+        )
+        .await?;
+        inspect(part);
+    }
+
+    Ok(size)
+}
+
+/// Uploads everything `reader` produces, replacing the remote file, and
+/// returns its size. Parts are sent as they fill up, so the total size does
+/// not need to be known in advance.
+pub async fn upload_reader(
+    client: &OpenApiClient,
+    mut reader: impl AsyncRead + Unpin,
+    remote_path: &str,
+) -> anyhow::Result<u64> {
+    let mut offset = 0;
+    loop {
+        let mut part = Vec::with_capacity(PART_SIZE as usize);
+        (&mut reader).take(PART_SIZE).read_to_end(&mut part).await?;
+        let length = part.len() as u64;
+        if offset == 0 {
+            fs::upload(client, remote_path, part, true).await?;
+        } else if length > 0 {
+            fs::write_at(client, remote_path, offset, Bytes::from(part)).await?;
+        }
+        offset += length;
+        if length < PART_SIZE {
+            return Ok(offset);
+        }
+    }
+}
+
+/// Uploads a local file so that readers never see it half-written.
+///
+/// The file is uploaded to a hidden temp sibling, its size is checked with
+/// `stat` and it is then moved onto `remote_path`, moving an existing file
+/// aside first if `mv` refuses to replace it. Without `overwrite` an
+/// existing `remote_path` is an error; it is checked before the upload and
+/// again before the move, so a file created in between may still be
+/// replaced. The temp file is removed when any step fails.
+pub async fn upload_file_atomic(
+    client: &OpenApiClient,
+    local_path: impl AsRef<Path>,
+    remote_path: &str,
+    overwrite: bool,
+) -> anyhow::Result<u64> {
+    let ensure_absent = || async {
+        if !overwrite && fs::stat_if_exists(client, remote_path).await?.is_some() {
+            bail!("{} already exists", remote_path);
+        }
+        Ok(())
+    };
+    ensure_absent().await?;
+
+    let temp_path = temp_sibling(remote_path)?;
+    let result = async {
+        let size = upload_file(client, local_path, &temp_path).await?;
+        let uploaded = fs::stat(client, &temp_path).await?.size as u64;
+        if uploaded != size {
+            bail!("uploaded {} has {} of {} bytes", temp_path, uploaded, size);
+        }
+        ensure_absent().await?;
+        replace_file(client, &temp_path, remote_path).await?;
+        Ok(size)
+    }
+    .await;
+
+    if result.is_err()
+        && let Err(e) = fs::remove(client, &temp_path).await
+    {
+        warn!("failed to remove temp file {}: {:#}", temp_path, e);
+    }
+    result
+}
+
+/// Moves `src_path` onto `dest_path`. The API does not document that `mv`
+/// replaces an existing destination, so when it fails and `dest_path` exists
+/// it is replaced with [`replace_aside`].
+pub(crate) async fn replace_file(
+    client: &OpenApiClient,
+    src_path: &str,
+    dest_path: &str,
+) -> anyhow::Result<()> {
+    let Err(e) = fs::rename(client, src_path, dest_path).await else {
+        return Ok(());
+    };
+    if fs::stat_if_exists(client, dest_path).await?.is_none() {
+        return Err(e);
+    }
+    replace_aside(client, src_path, dest_path).await
+}
+
+/// Moves `src_path` onto the existing `dest_path` by renaming `dest_path`
+/// aside first. The old entry is put back when the move fails and removed
+/// once it succeeds.
+pub(crate) async fn replace_aside(
+    client: &OpenApiClient,
+    src_path: &str,
+    dest_path: &str,
+) -> anyhow::Result<()> {
+    let aside_path = temp_sibling(dest_path)?;
+    fs::rename(client, dest_path, &aside_path).await?;
+    if let Err(e) = fs::rename(client, src_path, dest_path).await {
+        if let Err(restore_error) = fs::rename(client, &aside_path, dest_path).await {
+            warn!(
+                "failed to restore {} from {}: {:#}",
+                dest_path, aside_path, restore_error
+            );
+        }
+        return Err(e);
+    }
+    if let Err(e) = fs::remove(client, &aside_path).await {
+        warn!("failed to remove replaced {}: {:#}", aside_path, e);
+    }
+    Ok(())
+}
+
+/// A hidden path next to `remote_path` that is unique per process and call.
+pub(crate) fn temp_sibling(remote_path: &str) -> anyhow::Result<String> {
+    let path = RemotePath::new(remote_path)?;
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        bail!("cannot upload to {}", remote_path);
+    };
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let temp_name = format!(".{}.{}-{}.tmp", name, std::process::id(), nanos);
+    Ok(parent.join(temp_name)?.to_string())
+}
+
+/// Streams a remote file into a local file and returns the number of bytes written.
+pub async fn download_file(
+    client: &OpenApiClient,
+    remote_path: &str,
+    local_path: impl AsRef<Path>,
+) -> anyhow::Result<u64> {
+    download_file_inspect(client, remote_path, local_path, |_| {}).await
+}
+
+/// Like [`download_file`], calling `inspect` with every chunk in file order once it is written.
+pub async fn download_file_inspect(
+    client: &OpenApiClient,
+    remote_path: &str,
+    local_path: impl AsRef<Path>,
+    mut inspect: impl FnMut(&[u8]),
+) -> anyhow::Result<u64> {
+    let http_fn = ApiStorageDownloadRequest::new()
+        .with_path(RemotePath::new(remote_path)?)
+        .stream_builder();
+    let response = client.clone().send(http_fn).await?;
+    let mut stream = client.throttle(response.stream.ok_or_else(|| anyhow!("stream not found"))?);
+
+    let mut file = File::create(local_path.as_ref()).await?;
+    let mut written = 0u64;
+    while let Some(data) = stream.next().await {
+        let data = data?;
+        file.write_all(&data).await?;
+        inspect(&data);
+        written += data.len() as u64;
+    }
+    file.flush().await?;
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_sibling() {
+        let temp_path = temp_sibling("/u1/results/out.dat").unwrap();
+        let name = temp_path.strip_prefix("/u1/results/.out.dat.").unwrap();
+        assert!(name.ends_with(".tmp"));
+        assert!(temp_sibling("/").is_err());
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_upload_file_atomic_overwrite() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+
+        let storage = TestStorage::start().await?;
+        let client = &storage.client;
+        let local_path = storage.root.join("out.dat");
+        fs::upload(client, "/u1/out.dat", b"old".to_vec(), true).await?;
+
+        tokio::fs::write(&local_path, "new content").await?;
+        assert!(
+            upload_file_atomic(client, &local_path, "/u1/out.dat", false)
+                .await
+                .is_err()
+        );
+        assert_eq!(fs::download(client, "/u1/out.dat").await?, "old");
+
+        assert_eq!(
+            upload_file_atomic(client, &local_path, "/u1/out.dat", true).await?,
+            11
+        );
+        assert_eq!(fs::download(client, "/u1/out.dat").await?, "new content");
+        let names: Vec<String> = fs::list_dir(client, "/u1")
+            .await?
+            .into_iter()
+            .map(|file_info| file_info.name)
+            .collect();
+        assert_eq!(names, ["out.dat"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,413 @@
+use crate::common::client::OpenApiClient;
+use crate::common::remote_path::RemotePath;
+use crate::storage::fs;
+use anyhow::bail;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+const TRASH_DIR: &str = ".trash";
+const FILES_DIR: &str = "files";
+const MANIFEST_NAME: &str = "manifest.json";
+const ID_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub original_path: String,
+    /// Where the entry is kept until it is restored or purged.
+    pub trash_path: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// One call to [`Trash::remove`], stored as `manifest.json` next to the
+/// `files/` directory holding the removed entries.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashManifest {
+    /// The batch directory name, a UTC timestamp such as `20250102T030405.678Z`.
+    pub id: String,
+    pub deleted_at: DateTime<Utc>,
+    pub entries: Vec<TrashEntry>,
+}
+
+/// A safe alternative to `ApiStorageRemoveRequest` that moves entries into
+/// `<home>/.trash/<timestamp>/files/` instead of deleting them, keeping their
+/// path relative to the user root so that they can be restored.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    client: OpenApiClient,
+    home: RemotePath,
+    root: RemotePath,
+}
+
+impl Trash {
+    pub fn new(client: &OpenApiClient, home: RemotePath) -> anyhow::Result<Self> {
+        let root = home.join(TRASH_DIR)?;
+        Ok(Self {
+            client: client.clone(),
+            home,
+            root,
+        })
+    }
+
+    /// Moves `paths` into one new trash batch. The manifest is written before
+    /// anything is moved and rewritten to list only the entries moved before
+    /// a failure, so a partial removal can still be restored; if that rewrite
+    /// fails too, the moved entries are put back and the batch is removed
+    /// unless some of them could not be put back.
+    pub async fn remove(&self, paths: &[&str]) -> anyhow::Result<TrashManifest> {
+        let (id, deleted_at, batch) = self.new_batch().await?;
+        let files = batch.join(FILES_DIR)?;
+        let mut manifest = TrashManifest {
+            id,
+            deleted_at,
+            entries: Vec::new(),
+        };
+        let mut targets = Vec::new();
+        for path in paths {
+            let path = RemotePath::new(path)?;
+            let trash_path = files.join(self.relative(&path)?)?;
+            let file_info = fs::stat(&self.client, path.as_str()).await?;
+            manifest.entries.push(TrashEntry {
+                original_path: path.to_string(),
+                trash_path: trash_path.to_string(),
+                is_dir: file_info.is_dir,
+                size: file_info.size as u64,
+            });
+            targets.push((path, trash_path));
+        }
+        if let Err(e) = self.write_manifest(&batch, &manifest).await {
+            self.discard_batch(&batch).await;
+            return Err(e);
+        }
+
+        for (moved, (path, trash_path)) in targets.iter().enumerate() {
+            let Err(e) = self.move_entry(path, trash_path).await else {
+                continue;
+            };
+            manifest.entries.truncate(moved);
+            let Err(manifest_error) = self.write_manifest(&batch, &manifest).await else {
+                return Err(e);
+            };
+            warn!(
+                "rolling back trash batch {}: {:#}",
+                manifest.id, manifest_error
+            );
+            let mut rollback_errors = Vec::new();
+            for (path, trash_path) in targets[..moved].iter().rev() {
+                if let Err(rollback_error) = self.move_entry(trash_path, path).await {
+                    rollback_errors.push(format!("{}: {:#}", path, rollback_error));
+                }
+            }
+            if rollback_errors.is_empty() {
+                self.discard_batch(&batch).await;
+                return Err(e);
+            }
+            return Err(e.context(format!(
+                "trash batch {} kept, failed to put back {}",
+                manifest.id,
+                rollback_errors.join("; ")
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// Every batch with a readable manifest, oldest first.
+    pub async fn list(&self) -> anyhow::Result<Vec<TrashManifest>> {
+        let mut manifests = Vec::new();
+        for id in self.batch_ids().await? {
+            match self.manifest(&id).await {
+                Ok(manifest) => manifests.push(manifest),
+                Err(e) => warn!("skipping trash batch {}: {:#}", id, e),
+            }
+        }
+        Ok(manifests)
+    }
+
+    pub async fn manifest(&self, id: &str) -> anyhow::Result<TrashManifest> {
+        let path = self.root.join(id)?.join(MANIFEST_NAME)?;
+        let content = fs::download(&self.client, path.as_str()).await?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Moves every entry of batch `id` back to its original path and removes
+    /// the batch. Nothing is moved if any original path is taken again; when a
+    /// move fails, the manifest is rewritten to list the entries left in the
+    /// batch so that restoring can be retried.
+    pub async fn restore(&self, id: &str) -> anyhow::Result<TrashManifest> {
+        let manifest = self.manifest(id).await?;
+        for entry in &manifest.entries {
+            if fs::stat_if_exists(&self.client, &entry.original_path)
+                .await?
+                .is_some()
+            {
+                bail!(
+                    "cannot restore {} from trash batch {}: the path exists",
+                    entry.original_path,
+                    id
+                );
+            }
+        }
+        let batch = self.root.join(id)?;
+        for (restored, entry) in manifest.entries.iter().enumerate() {
+            let original_path = RemotePath::new(&entry.original_path)?;
+            let trash_path = RemotePath::new(&entry.trash_path)?;
+            let Err(e) = self.move_entry(&trash_path, &original_path).await else {
+                continue;
+            };
+            let remaining = TrashManifest {
+                entries: manifest.entries[restored..].to_vec(),
+                ..manifest.clone()
+            };
+            if let Err(manifest_error) = self.write_manifest(&batch, &remaining).await {
+                return Err(e.context(format!(
+                    "failed to update the manifest of trash batch {}: {:#}",
+                    id, manifest_error
+                )));
+            }
+            return Err(e);
+        }
+        fs::remove(&self.client, batch.as_str()).await?;
+        Ok(manifest)
+    }
+
+    /// Permanently removes batches deleted more than `retention` ago and
+    /// returns their ids. The age is taken from the batch name, so batches
+    /// with a broken manifest are purged too.
+    pub async fn purge(&self, retention: Duration) -> anyhow::Result<Vec<String>> {
+        let cutoff = Utc::now() - chrono::Duration::from_std(retention)?;
+        let mut purged = Vec::new();
+        for id in self.batch_ids().await? {
+            if parse_id(&id).is_some_and(|deleted_at| deleted_at < cutoff) {
+                fs::remove(&self.client, self.root.join(&id)?.as_str()).await?;
+                purged.push(id);
+            }
+        }
+        Ok(purged)
+    }
+
+    /// The path of `path` below the user root, refusing the root itself and
+    /// anything inside the trash.
+    fn relative(&self, path: &RemotePath) -> anyhow::Result<String> {
+        let Some(relative) = path
+            .as_str()
+            .strip_prefix(self.home.as_str())
+            .and_then(|relative| relative.strip_prefix('/'))
+        else {
+            bail!("cannot trash {}: not below {}", path, self.home);
+        };
+        if relative == TRASH_DIR || relative.starts_with(&format!("{}/", TRASH_DIR)) {
+            bail!("cannot trash {}: already in the trash", path);
+        }
+        Ok(relative.to_string())
+    }
+
+    async fn new_batch(&self) -> anyhow::Result<(String, DateTime<Utc>, RemotePath)> {
+        loop {
+            let deleted_at = Utc::now();
+            let id = deleted_at.format(ID_FORMAT).to_string();
+            let batch = self.root.join(&id)?;
+            if fs::stat_if_exists(&self.client, batch.as_str())
+                .await?
+                .is_none()
+            {
+                fs::mkdir_all(&self.client, batch.join(FILES_DIR)?.as_str()).await?;
+                return Ok((id, deleted_at, batch));
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    async fn move_entry(&self, src: &RemotePath, dest: &RemotePath) -> anyhow::Result<()> {
+        if let Some(parent) = dest.parent() {
+            fs::mkdir_all(&self.client, parent.as_str()).await?;
+        }
+        fs::rename(&self.client, src.as_str(), dest.as_str()).await
+    }
+
+    async fn write_manifest(
+        &self,
+        batch: &RemotePath,
+        manifest: &TrashManifest,
+    ) -> anyhow::Result<()> {
+        let path = batch.join(MANIFEST_NAME)?;
+        let content = serde_json::to_vec_pretty(manifest)?;
+        fs::upload(&self.client, path.as_str(), content, true).await
+    }
+
+    async fn discard_batch(&self, batch: &RemotePath) {
+        if let Err(e) = fs::remove(&self.client, batch.as_str()).await {
+            warn!("failed to remove trash batch {}: {:#}", batch, e);
+        }
+    }
+
+    async fn batch_ids(&self) -> anyhow::Result<Vec<String>> {
+        if fs::stat_if_exists(&self.client, self.root.as_str())
+            .await?
+            .is_none()
+        {
+            return Ok(Vec::new());
+        }
+        let mut ids: Vec<String> = fs::list_dir(&self.client, self.root.as_str())
+            .await?
+            .into_iter()
+            .filter(|file_info| file_info.is_dir)
+            .map(|file_info| file_info.name)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+fn parse_id(id: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(id, ID_FORMAT)
+        .ok()
+        .map(|deleted_at| deleted_at.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::OpenApiConfig;
+
+    #[test]
+    fn test_parse_id() {
+        let deleted_at = DateTime::from_timestamp_millis(1_735_787_045_678).unwrap();
+        let id = deleted_at.format(ID_FORMAT).to_string();
+        assert_eq!(id, "20250102T030405.678Z");
+        assert_eq!(parse_id(&id), Some(deleted_at));
+        assert_eq!(parse_id("notes"), None);
+    }
+
+    #[test]
+    fn test_relative() {
+        let client = OpenApiClient::new(OpenApiConfig::new());
+        let trash = Trash::new(&client, RemotePath::new("/u1").unwrap()).unwrap();
+        let path = |path: &str| RemotePath::new(path).unwrap();
+        assert_eq!(
+            trash.relative(&path("/u1/results/a.dat")).unwrap(),
+            "results/a.dat"
+        );
+        assert!(trash.relative(&path("/u1")).is_err());
+        assert!(trash.relative(&path("/u10/a.dat")).is_err());
+        assert!(trash.relative(&path("/u1/.trash")).is_err());
+        assert!(trash.relative(&path("/u1/.trash/x/files/a")).is_err());
+        assert!(trash.relative(&path("/u1/.trashy")).is_ok());
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_remove_fails_when_manifest_write_fails() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+
+        let storage = TestStorage::start_with(|router| {
+            router.layer(middleware::from_fn(
+                |request: Request, next: Next| async move {
+                    let query = request.uri().query().unwrap_or_default();
+                    if request.uri().path() == "/api/storage/upload/file"
+                        && query.contains(MANIFEST_NAME)
+                    {
+                        return (StatusCode::SERVICE_UNAVAILABLE, "unavailable").into_response();
+                    }
+                    next.run(request).await
+                },
+            ))
+        })
+        .await?;
+        let client = &storage.client;
+        fs::upload(client, "/u1/a.dat", b"a".to_vec(), true).await?;
+
+        let trash = Trash::new(client, RemotePath::new("/u1")?)?;
+        assert!(trash.remove(&["/u1/a.dat"]).await.is_err());
+        assert_eq!(fs::download(client, "/u1/a.dat").await?, "a");
+        assert!(trash.list().await?.is_empty());
+        assert!(trash.batch_ids().await?.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_remove_and_restore() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+
+        let storage = TestStorage::start().await?;
+        let client = &storage.client;
+        fs::mkdir_all(client, "/u1/results").await?;
+        fs::upload(client, "/u1/results/a.dat", b"a".to_vec(), true).await?;
+
+        let trash = Trash::new(client, RemotePath::new("/u1")?)?;
+        let manifest = trash.remove(&["/u1/results/a.dat"]).await?;
+        assert!(
+            fs::stat_if_exists(client, "/u1/results/a.dat")
+                .await?
+                .is_none()
+        );
+        assert_eq!(trash.list().await?, std::slice::from_ref(&manifest));
+
+        trash.restore(&manifest.id).await?;
+        assert_eq!(fs::download(client, "/u1/results/a.dat").await?, "a");
+        assert!(trash.list().await?.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_restore_resumes_after_failed_move() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::body::{Body, to_bytes};
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // fails moving b.dat back while `fail` is set
+        let fail = Arc::new(AtomicBool::new(false));
+        let storage = TestStorage::start_with({
+            let fail = fail.clone();
+            move |router| {
+                router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                    let fail = fail.clone();
+                    async move {
+                        if !fail.load(Ordering::Relaxed)
+                            || request.uri().path() != "/api/storage/mv"
+                        {
+                            return next.run(request).await;
+                        }
+                        let (parts, body) = request.into_parts();
+                        let body = to_bytes(body, usize::MAX).await.unwrap();
+                        if String::from_utf8_lossy(&body).contains("\"Dest\":\"/u1/b.dat\"") {
+                            return (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                                .into_response();
+                        }
+                        next.run(Request::from_parts(parts, Body::from(body))).await
+                    }
+                }))
+            }
+        })
+        .await?;
+        let client = &storage.client;
+        fs::upload(client, "/u1/a.dat", b"a".to_vec(), true).await?;
+        fs::upload(client, "/u1/b.dat", b"b".to_vec(), true).await?;
+
+        let trash = Trash::new(client, RemotePath::new("/u1")?)?;
+        let manifest = trash.remove(&["/u1/a.dat", "/u1/b.dat"]).await?;
+        fail.store(true, Ordering::Relaxed);
+        assert!(trash.restore(&manifest.id).await.is_err());
+        assert_eq!(fs::download(client, "/u1/a.dat").await?, "a");
+        let remaining = trash.manifest(&manifest.id).await?;
+        assert_eq!(remaining.entries, manifest.entries[1..]);
+
+        fail.store(false, Ordering::Relaxed);
+        trash.restore(&manifest.id).await?;
+        assert_eq!(fs::download(client, "/u1/b.dat").await?, "b");
+        assert!(trash.list().await?.is_empty());
+        Ok(())
+    }
+}
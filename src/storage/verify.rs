@@ -0,0 +1,489 @@
+use crate::common::client::OpenApiClient;
+use crate::storage::range::ByteRange;
+use crate::storage::{fs, transfer};
+use bytes::Bytes;
+use md5::{Digest, Md5};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom};
+use tracing::warn;
+
+const DEFAULT_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+const DEFAULT_MAX_RETRIES: usize = 3;
+
+pub type BlockDigests = Vec<(ByteRange, Vec<u8>)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// Compare the MD5 of every block with the checksum API.
+    ChunkChecksums { block_size: u64 },
+    /// Compare the MD5 of the whole file with `expected`, or with the checksum
+    /// API using a single block when no hash is known in advance.
+    WholeFile { expected: Option<Vec<u8>> },
+}
+
+impl Default for Verification {
+    fn default() -> Self {
+        Verification::ChunkChecksums {
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+pub enum IntegrityError {
+    #[display("checksum mismatch for {path} in {range}")]
+    ChecksumMismatch { path: String, range: ByteRange },
+    #[display("size mismatch for {path}: expected {expected} bytes, found {actual}")]
+    SizeMismatch {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+/// Computes the MD5 of every `block_size` block and of the whole input as
+/// data is fed in arbitrary pieces.
+#[derive(Debug, Clone)]
+pub struct BlockHasher {
+    block_size: u64,
+    offset: u64,
+    block_len: u64,
+    block: Md5,
+    whole: Md5,
+    blocks: BlockDigests,
+}
+
+impl BlockHasher {
+    pub fn new(block_size: u64) -> Self {
+        Self {
+            block_size: block_size.max(1),
+            offset: 0,
+            block_len: 0,
+            block: Md5::new(),
+            whole: Md5::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.whole.update(data);
+        while !data.is_empty() {
+            let take = ((self.block_size - self.block_len) as usize).min(data.len());
+            self.block.update(&data[..take]);
+            self.block_len += take as u64;
+            data = &data[take..];
+            if self.block_len == self.block_size {
+                self.flush_block();
+            }
+        }
+    }
+
+    fn flush_block(&mut self) {
+        let digest = self.block.finalize_reset().to_vec();
+        self.blocks
+            .push((ByteRange::new(self.offset, self.block_len), digest));
+        self.offset += self.block_len;
+        self.block_len = 0;
+    }
+
+    /// Returns the per-block digests and the digest of the whole input.
+    pub fn finish(mut self) -> (BlockDigests, Vec<u8>) {
+        if self.block_len > 0 {
+            self.flush_block();
+        }
+        (self.blocks, self.whole.finalize().to_vec())
+    }
+}
+
+/// Transfers files and checks that both sides hold the same bytes afterwards,
+/// re-transferring mismatched ranges up to `max_retries` times.
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    client: OpenApiClient,
+    verification: Verification,
+    max_retries: usize,
+}
+
+impl Verifier {
+    pub fn new(client: OpenApiClient) -> Self {
+        Self {
+            client,
+            verification: Verification::default(),
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    pub fn with_verification(mut self, verification: Verification) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    fn block_size(&self, size: u64) -> u64 {
+        match self.verification {
+            Verification::ChunkChecksums { block_size } => block_size.max(1),
+            Verification::WholeFile { .. } => size.max(1),
+        }
+    }
+
+    fn reference(&self, blocks: BlockDigests, whole: Vec<u8>, size: u64) -> BlockDigests {
+        match &self.verification {
+            Verification::ChunkChecksums { .. } => blocks,
+            Verification::WholeFile { expected } => {
+                vec![(ByteRange::new(0, size), expected.clone().unwrap_or(whole))]
+            }
+        }
+    }
+
+    async fn remote_digests(
+        &self,
+        remote_path: &str,
+        block_size: u64,
+    ) -> anyhow::Result<BlockDigests> {
+        remote_digests(&self.client, remote_path, block_size).await
+    }
+
+    /// Uploads `local_path` and verifies the remote copy, returning the file size.
+    pub async fn upload(
+        &self,
+        local_path: impl AsRef<Path>,
+        remote_path: &str,
+    ) -> anyhow::Result<u64> {
+        let local_path = local_path.as_ref();
+        let size = tokio::fs::metadata(local_path).await?.len();
+        let block_size = self.block_size(size);
+
+        let mut hasher = BlockHasher::new(block_size);
+        transfer::upload_file_inspect(&self.client, local_path, remote_path, |data| {
+            hasher.update(data)
+        })
+        .await?;
+        let (blocks, whole) = hasher.finish();
+        let reference = self.reference(blocks, whole, size);
+
+        let mut attempt = 0;
+        loop {
+            let remote_size = fs::stat(&self.client, remote_path).await?.size as u64;
+            if remote_size != size {
+                return Err(IntegrityError::SizeMismatch {
+                    path: remote_path.to_string(),
+                    expected: size,
+                    actual: remote_size,
+                }
+                .into());
+            }
+            // The checksum API returns no blocks for an empty file, so the sizes are all there is to compare.
+            if size == 0 {
+                return Ok(size);
+            }
+            let actual = self.remote_digests(remote_path, block_size).await?;
+            let mismatched = mismatched_ranges(&reference, &actual);
+            let Some(&range) = mismatched.first() else {
+                return Ok(size);
+            };
+            if attempt == self.max_retries {
+                return Err(IntegrityError::ChecksumMismatch {
+                    path: remote_path.to_string(),
+                    range,
+                }
+                .into());
+            }
+            attempt += 1;
+            let mut file = File::open(local_path).await?;
+            for range in mismatched {
+                warn!(
+                    "re-uploading {} {} after checksum mismatch",
+                    remote_path, range
+                );
+                resend_range(&self.client, &mut file, remote_path, range).await?;
+            }
+        }
+    }
+
+    /// Downloads `remote_path` and verifies the local copy, returning the file size.
+    pub async fn download(
+        &self,
+        remote_path: &str,
+        local_path: impl AsRef<Path>,
+    ) -> anyhow::Result<u64> {
+        let local_path = local_path.as_ref();
+        let size = fs::stat(&self.client, remote_path).await?.size as u64;
+        let block_size = self.block_size(size);
+
+        let mut hasher = BlockHasher::new(block_size);
+        transfer::download_file_inspect(&self.client, remote_path, local_path, |data| {
+            hasher.update(data)
+        })
+        .await?;
+        let (blocks, whole) = hasher.finish();
+        let mut file = OpenOptions::new().write(true).open(local_path).await?;
+        file.set_len(size).await?;
+        if size == 0 {
+            return Ok(size);
+        }
+        let reference = match &self.verification {
+            Verification::WholeFile {
+                expected: Some(expected),
+            } => vec![(ByteRange::new(0, size), expected.clone())],
+            _ => self.remote_digests(remote_path, block_size).await?,
+        };
+        let actual = match self.verification {
+            Verification::ChunkChecksums { .. } => blocks,
+            Verification::WholeFile { .. } => vec![(ByteRange::new(0, size), whole)],
+        };
+
+        let mismatched = mismatched_ranges(&reference, &actual);
+        for (range, digest) in reference
+            .iter()
+            .filter(|(range, _)| mismatched.contains(range))
+        {
+            let mut attempt = 0;
+            loop {
+                if attempt == self.max_retries {
+                    return Err(IntegrityError::ChecksumMismatch {
+                        path: remote_path.to_string(),
+                        range: *range,
+                    }
+                    .into());
+                }
+                attempt += 1;
+                warn!(
+                    "re-downloading {} {} after checksum mismatch",
+                    remote_path, range
+                );
+                if refetch_range(&self.client, remote_path, &mut file, *range).await? == *digest {
+                    break;
+                }
+            }
+        }
+        file.flush().await?;
+
+        Ok(size)
+    }
+}
+
+/// Fetches the per-block MD5 digests of a remote file from the checksum API.
+pub(crate) async fn remote_digests(
+    client: &OpenApiClient,
+    remote_path: &str,
+    block_size: u64,
+) -> anyhow::Result<BlockDigests> {
+    Ok(fs::chunk_checksums(client, remote_path, block_size, None)
+        .await?
+        .into_iter()
+        .map(|checksum| {
+            let range = ByteRange::new(checksum.chunk_offset as u64, checksum.size as u64);
+            (range, checksum.strong_checksum)
+        })
+        .collect())
+}
+
+/// Returns the reference ranges whose digest is missing or different in `actual`.
+pub(crate) fn mismatched_ranges(
+    reference: &[(ByteRange, Vec<u8>)],
+    actual: &[(ByteRange, Vec<u8>)],
+) -> Vec<ByteRange> {
+    let actual: HashMap<ByteRange, &Vec<u8>> = actual
+        .iter()
+        .map(|(range, digest)| (*range, digest))
+        .collect();
+    reference
+        .iter()
+        .filter(|(range, digest)| actual.get(range) != Some(&digest))
+        .map(|(range, _)| *range)
+        .collect()
+}
+
+async fn resend_range(
+    client: &OpenApiClient,
+    file: &mut File,
+    remote_path: &str,
+    range: ByteRange,
+) -> anyhow::Result<()> {
+    file.seek(SeekFrom::Start(range.offset)).await?;
+    for part in ByteRange::split(range.length, transfer::PART_SIZE) {
+        let mut buffer = vec![0u8; part.length as usize];
+        file.read_exact(&mut buffer).await?;
+        fs::write_at(
+            client,
+            remote_path,
+            range.offset + part.offset,
+            Bytes::from(buffer),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Reads `range` again with `readAt`, writes it into `file` and returns its MD5.
+async fn refetch_range(
+    client: &OpenApiClient,
+    remote_path: &str,
+    file: &mut File,
+    range: ByteRange,
+) -> anyhow::Result<Vec<u8>> {
+    let mut hasher = Md5::new();
+    file.seek(SeekFrom::Start(range.offset)).await?;
+    for part in ByteRange::split(range.length, transfer::PART_SIZE) {
+        let data =
+            fs::read_at(client, remote_path, range.offset + part.offset, part.length).await?;
+        hasher.update(&data);
+        file.write_all(&data).await?;
+    }
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::crypt::rolling::strong_checksum;
+
+    #[test]
+    fn test_block_hasher() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut hasher = BlockHasher::new(300);
+        for piece in data.chunks(77) {
+            hasher.update(piece);
+        }
+        let (blocks, whole) = hasher.finish();
+        let expected: Vec<(ByteRange, Vec<u8>)> = ByteRange::split(1000, 300)
+            .into_iter()
+            .map(|range| {
+                let digest = strong_checksum(&data[range.offset as usize..range.end() as usize]);
+                (range, digest)
+            })
+            .collect();
+        assert_eq!(blocks, expected);
+        assert_eq!(whole, strong_checksum(&data));
+    }
+
+    #[test]
+    fn test_mismatched_ranges() {
+        let reference = vec![
+            (ByteRange::new(0, 4), vec![1]),
+            (ByteRange::new(4, 4), vec![2]),
+            (ByteRange::new(8, 2), vec![3]),
+        ];
+        let actual = vec![
+            (ByteRange::new(0, 4), vec![1]),
+            (ByteRange::new(4, 4), vec![9]),
+        ];
+        assert_eq!(
+            mismatched_ranges(&reference, &actual),
+            vec![ByteRange::new(4, 4), ByteRange::new(8, 2)]
+        );
+        assert!(mismatched_ranges(&reference, &reference).is_empty());
+    }
+
+    #[test]
+    fn test_integrity_error() {
+        let error = IntegrityError::ChecksumMismatch {
+            path: "/u1/out.dat".to_string(),
+            range: ByteRange::new(4, 4),
+        };
+        assert_eq!(
+            error.to_string(),
+            "checksum mismatch for /u1/out.dat in [4, 8)"
+        );
+        let error: anyhow::Error = error.into();
+        assert!(error.downcast_ref::<IntegrityError>().is_some());
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_verify_empty_file() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+
+        let storage = TestStorage::start().await?;
+        let local_path = storage.root.join("empty.dat");
+        tokio::fs::write(&local_path, "").await?;
+        for verification in [
+            Verification::WholeFile { expected: None },
+            Verification::default(),
+        ] {
+            let verifier = Verifier::new(storage.client.clone())
+                .with_verification(verification)
+                .with_max_retries(0);
+            assert_eq!(verifier.upload(&local_path, "/u1/empty.dat").await?, 0);
+            let copy_path = storage.root.join("empty.copy");
+            assert_eq!(verifier.download("/u1/empty.dat", &copy_path).await?, 0);
+            assert!(tokio::fs::read(&copy_path).await?.is_empty());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_verify_repairs_corrupted_blocks() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::body::{Body, to_bytes};
+        use axum::extract::Request;
+        use axum::middleware::{self, Next};
+        use axum::response::Response;
+
+        // corrupts the second block of every whole-file upload and download,
+        // leaving the writeAt and readAt repairs alone
+        let storage = TestStorage::start_with(|router| {
+            router.layer(middleware::from_fn(
+                |request: Request, next: Next| async move {
+                    let path = request.uri().path().to_string();
+                    let request = if path == "/api/storage/upload/file" {
+                        let (parts, body) = request.into_parts();
+                        let mut data = to_bytes(body, usize::MAX).await.unwrap().to_vec();
+                        let at = data.windows(4).position(|w| w == b"bbbb").unwrap();
+                        data[at] = b'x';
+                        Request::from_parts(parts, Body::from(data))
+                    } else {
+                        request
+                    };
+                    let response = next.run(request).await;
+                    if path != "/api/storage/download" {
+                        return response;
+                    }
+                    let (parts, body) = response.into_parts();
+                    let mut data = to_bytes(body, usize::MAX).await.unwrap().to_vec();
+                    data[4] ^= 0xff;
+                    Response::from_parts(parts, Body::from(data))
+                },
+            ))
+        })
+        .await?;
+        let client = &storage.client;
+        let local_path = storage.root.join("deck.inp");
+        tokio::fs::write(&local_path, "aaaabbbbcc").await?;
+        let verifier = Verifier::new(client.clone())
+            .with_verification(Verification::ChunkChecksums { block_size: 4 });
+
+        assert_eq!(verifier.upload(&local_path, "/u1/deck.inp").await?, 10);
+        assert_eq!(
+            fs::read_at(client, "/u1/deck.inp", 0, 10).await?,
+            "aaaabbbbcc"
+        );
+        let copy_path = storage.root.join("deck.copy");
+        assert_eq!(verifier.download("/u1/deck.inp", &copy_path).await?, 10);
+        assert_eq!(tokio::fs::read(&copy_path).await?, b"aaaabbbbcc");
+
+        let verifier = verifier.with_max_retries(0);
+        let mismatch = IntegrityError::ChecksumMismatch {
+            path: "/u1/deck.inp".to_string(),
+            range: ByteRange::new(4, 4),
+        };
+        let error = verifier
+            .upload(&local_path, "/u1/deck.inp")
+            .await
+            .expect_err("corrupted upload");
+        assert_eq!(error.downcast_ref::<IntegrityError>(), Some(&mismatch));
+        let error = verifier
+            .download("/u1/deck.inp", &copy_path)
+            .await
+            .expect_err("corrupted download");
+        assert_eq!(error.downcast_ref::<IntegrityError>(), Some(&mismatch));
+        Ok(())
+    }
+}
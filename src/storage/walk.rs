@@ -0,0 +1,355 @@
+use crate::common::client::OpenApiClient;
+use crate::model::file::FileInfo;
+use crate::storage::fs;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Stream, StreamExt, stream};
+use regex::Regex;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+pub type WalkStream = Pin<Box<dyn Stream<Item = anyhow::Result<(String, FileInfo)>> + Send>>;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WalkOrder {
+    #[default]
+    BreadthFirst,
+    DepthFirst,
+}
+
+/// Recursively lists a remote directory. Every entry below the root is
+/// yielded with its full remote path; predicates only filter what is
+/// yielded, directories are always descended into up to `max_depth`.
+#[derive(Debug, Clone)]
+pub struct Walker {
+    client: OpenApiClient,
+    root: String,
+    max_depth: Option<usize>,
+    order: WalkOrder,
+    concurrency: usize,
+    files_only: bool,
+    name_regex: Option<Regex>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<DateTime<Utc>>,
+    modified_before: Option<DateTime<Utc>>,
+}
+
+pub fn walk(client: &OpenApiClient, path: &str) -> Walker {
+    Walker {
+        client: client.clone(),
+        root: path.to_string(),
+        max_depth: None,
+        order: WalkOrder::default(),
+        concurrency: DEFAULT_CONCURRENCY,
+        files_only: false,
+        name_regex: None,
+        min_size: None,
+        max_size: None,
+        modified_after: None,
+        modified_before: None,
+    }
+}
+
+impl Walker {
+    /// Limits recursion: a depth of 1 lists only the root's direct children.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn with_order(mut self, order: WalkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Lists up to `concurrency` directories at once. Above one, the yielded
+    /// order only approximates the requested `WalkOrder`.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_files_only(mut self, files_only: bool) -> Self {
+        self.files_only = files_only;
+        self
+    }
+
+    pub fn with_name_regex(mut self, name_regex: Regex) -> Self {
+        self.name_regex = Some(name_regex);
+        self
+    }
+
+    /// Size bounds apply to files, directories are not filtered by size.
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = Some(min_size);
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn with_modified_after(mut self, modified_after: DateTime<Utc>) -> Self {
+        self.modified_after = Some(modified_after);
+        self
+    }
+
+    pub fn with_modified_before(mut self, modified_before: DateTime<Utc>) -> Self {
+        self.modified_before = Some(modified_before);
+        self
+    }
+
+    fn matches(&self, file_info: &FileInfo) -> bool {
+        if self.files_only && file_info.is_dir {
+            return false;
+        }
+        if let Some(name_regex) = &self.name_regex
+            && !name_regex.is_match(&file_info.name)
+        {
+            return false;
+        }
+        if !file_info.is_dir {
+            let size = file_info.size as u64;
+            if self.min_size.is_some_and(|min_size| size < min_size)
+                || self.max_size.is_some_and(|max_size| size > max_size)
+            {
+                return false;
+            }
+        }
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let Some(modified) = file_info.modified() else {
+                return false;
+            };
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self.modified_before.is_some_and(|before| modified > before)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn descends(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth < max_depth)
+    }
+
+    pub fn into_stream(self) -> WalkStream {
+        let state = WalkState {
+            pending: VecDeque::from([(self.root.clone(), 0)]),
+            walker: Arc::new(self),
+            listing: FuturesUnordered::new(),
+            ready: VecDeque::new(),
+        };
+        Box::pin(stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(item) = state.ready.pop_front() {
+                    return Some((item, state));
+                }
+                state.fill();
+                let (dir, depth, files) = state.listing.next().await?;
+                match files {
+                    Ok(files) => state.push_listing(&dir, depth, files),
+                    Err(e) => state
+                        .ready
+                        .push_back(Err(e.context(format!("list {}", dir)))),
+                }
+            }
+        }))
+    }
+}
+
+type Listing = (String, usize, anyhow::Result<Vec<FileInfo>>);
+
+struct WalkState {
+    walker: Arc<Walker>,
+    pending: VecDeque<(String, usize)>,
+    listing: FuturesUnordered<BoxFuture<'static, Listing>>,
+    ready: VecDeque<anyhow::Result<(String, FileInfo)>>,
+}
+
+impl WalkState {
+    fn fill(&mut self) {
+        while self.listing.len() < self.walker.concurrency {
+            let next = match self.walker.order {
+                WalkOrder::BreadthFirst => self.pending.pop_front(),
+                WalkOrder::DepthFirst => self.pending.pop_back(),
+            };
+            let Some((dir, depth)) = next else {
+                break;
+            };
+            let walker = self.walker.clone();
+            self.listing.push(
+                async move {
+                    let files = fs::list_dir(&walker.client, &dir).await;
+                    (dir, depth, files)
+                }
+                .boxed(),
+            );
+        }
+    }
+
+    fn push_listing(&mut self, dir: &str, depth: usize, files: Vec<FileInfo>) {
+        let depth = depth + 1;
+        let mut subdirs = Vec::new();
+        for file_info in files {
+            let path = fs::join(dir, &file_info.name);
+            if file_info.is_dir && self.walker.descends(depth) {
+                subdirs.push((path.clone(), depth));
+            }
+            if self.walker.matches(&file_info) {
+                self.ready.push_back(Ok((path, file_info)));
+            }
+        }
+        match self.walker.order {
+            WalkOrder::BreadthFirst => self.pending.extend(subdirs),
+            WalkOrder::DepthFirst => self.pending.extend(subdirs.into_iter().rev()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_info(name: &str, size: isize, mod_time: &str, is_dir: bool) -> FileInfo {
+        FileInfo {
+            name: name.to_string(),
+            size,
+            mod_time: mod_time.to_string(),
+            is_dir,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_walker_matches() -> anyhow::Result<()> {
+        let walker = walk(&OpenApiClient::default(), "/u1")
+            .with_name_regex(Regex::new(r"\.log$")?)
+            .with_min_size(10)
+            .with_modified_after("2024-01-01T00:00:00Z".parse()?);
+        assert!(walker.matches(&file_info("a.log", 10, "2024-06-01T00:00:00Z", false)));
+        assert!(!walker.matches(&file_info("a.log", 9, "2024-06-01T00:00:00Z", false)));
+        assert!(!walker.matches(&file_info("a.txt", 10, "2024-06-01T00:00:00Z", false)));
+        assert!(!walker.matches(&file_info("a.log", 10, "2023-06-01T00:00:00Z", false)));
+        assert!(!walker.matches(&file_info("a.log", 10, "", false)));
+        assert!(walker.matches(&file_info("dir.log", 0, "2024-06-01T00:00:00Z", true)));
+        assert!(!walker.with_files_only(true).matches(&file_info(
+            "dir.log",
+            0,
+            "2024-06-01T00:00:00Z",
+            true
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_walker_descends() {
+        let walker = walk(&OpenApiClient::default(), "/u1");
+        assert!(walker.descends(100));
+        let walker = walker.with_max_depth(2);
+        assert!(walker.descends(1));
+        assert!(!walker.descends(2));
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_walk_stream() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::extract::Request;
+        use axum::http::StatusCode;
+        use axum::middleware::{self, Next};
+        use axum::response::IntoResponse;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let fail_b = Arc::new(AtomicBool::new(false));
+        let storage = TestStorage::start_with({
+            let fail_b = fail_b.clone();
+            move |router| {
+                router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                    let fail_b = fail_b.clone();
+                    async move {
+                        let query = request.uri().query().unwrap_or_default();
+                        if fail_b.load(Ordering::Relaxed) && query.contains("%2Fu1%2Ft%2Fb") {
+                            return (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                                .into_response();
+                        }
+                        next.run(request).await
+                    }
+                }))
+            }
+        })
+        .await?;
+        let local = storage.root.join("u1");
+        std::fs::create_dir_all(local.join("t/a/sub"))?;
+        std::fs::create_dir_all(local.join("t/b"))?;
+        std::fs::create_dir_all(local.join("big"))?;
+        for path in ["t/a/x.txt", "t/a/sub/y.txt", "t/b/z.txt", "t/c.txt"] {
+            std::fs::write(local.join(path), path)?;
+        }
+        // more entries than one lsWithPage page
+        for i in 0..1001 {
+            std::fs::write(local.join(format!("big/f{:04}", i)), "")?;
+        }
+
+        let paths = |walker: Walker| async move {
+            walker
+                .with_concurrency(1)
+                .into_stream()
+                .map(|item| item.map(|(path, _)| path))
+                .collect::<Vec<anyhow::Result<String>>>()
+                .await
+                .into_iter()
+                .collect::<anyhow::Result<Vec<String>>>()
+        };
+        let client = &storage.client;
+        assert_eq!(
+            paths(walk(client, "/u1/t")).await?,
+            [
+                "/u1/t/a",
+                "/u1/t/b",
+                "/u1/t/c.txt",
+                "/u1/t/a/sub",
+                "/u1/t/a/x.txt",
+                "/u1/t/b/z.txt",
+                "/u1/t/a/sub/y.txt",
+            ]
+        );
+        assert_eq!(
+            paths(walk(client, "/u1/t").with_order(WalkOrder::DepthFirst)).await?,
+            [
+                "/u1/t/a",
+                "/u1/t/b",
+                "/u1/t/c.txt",
+                "/u1/t/a/sub",
+                "/u1/t/a/x.txt",
+                "/u1/t/a/sub/y.txt",
+                "/u1/t/b/z.txt",
+            ]
+        );
+        assert_eq!(
+            paths(walk(client, "/u1/t").with_max_depth(1)).await?,
+            ["/u1/t/a", "/u1/t/b", "/u1/t/c.txt"]
+        );
+        assert_eq!(
+            paths(walk(client, "/u1/t").with_max_depth(2)).await?.len(),
+            6
+        );
+        assert_eq!(paths(walk(client, "/u1/big")).await?.len(), 1001);
+
+        fail_b.store(true, Ordering::Relaxed);
+        let items: Vec<_> = walk(client, "/u1/t").into_stream().collect().await;
+        let errors: Vec<String> = items
+            .iter()
+            .filter_map(|item| item.as_ref().err().map(|e| e.to_string()))
+            .collect();
+        assert_eq!(errors, ["list /u1/t/b"]);
+        assert_eq!(items.len(), 7);
+        Ok(())
+    }
+}
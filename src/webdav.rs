@@ -0,0 +1,629 @@
+use crate::api::v1::storage::api_storage_download::ApiStorageDownloadRequest;
+use crate::common::client::OpenApiClient;
+use crate::common::define::HttpStreamBuilder;
+use crate::common::remote_path::RemotePath;
+use crate::model::file::FileInfo;
+use crate::storage::range::ByteRange;
+use crate::storage::{fs, transfer};
+use anyhow::anyhow;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header};
+use axum::response::{IntoResponse, Response};
+use futures::{StreamExt, TryStreamExt, stream};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use std::fmt::{Display, Write};
+use std::io;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_util::io::StreamReader;
+use tracing::{debug, warn};
+
+/// Characters escaped in a path segment of an `href`.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+const ALLOW: &str = "OPTIONS, PROPFIND, GET, HEAD, PUT, DELETE, MKCOL, MOVE";
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// A WebDAV (class 1) server exposing the remote directory `root`, so that
+/// desktop tools can browse and edit storage through the storage API.
+///
+/// `PROPFIND` lists with `stat` and `lsWithPage`, `GET` streams `download`
+/// and streams a `Range` as `readAt` calls of at most `read_size` bytes,
+/// `PUT` streams the body through `upload` and `writeAt` into a hidden temp
+/// sibling that is moved into place once complete, and `MOVE`, `DELETE` and
+/// `MKCOL` map to `mv`, `rm` and `mkdir`. Locking and `COPY` are not
+/// supported, and a `PROPFIND` with `Depth: infinity` is answered as
+/// `Depth: 1`.
+#[derive(Debug, Clone)]
+pub struct WebDavGateway {
+    client: OpenApiClient,
+    root: RemotePath,
+    read_size: u64,
+}
+
+impl WebDavGateway {
+    pub fn new(client: &OpenApiClient, root: RemotePath) -> Self {
+        Self {
+            client: client.clone(),
+            root,
+            read_size: transfer::PART_SIZE,
+        }
+    }
+
+    pub fn with_read_size(mut self, read_size: u64) -> Self {
+        self.read_size = read_size.max(1);
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .fallback(handle)
+            .layer(DefaultBodyLimit::disable())
+            .with_state(Arc::new(self))
+    }
+
+    pub async fn serve(self, listener: TcpListener) -> anyhow::Result<()> {
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    /// Maps a request path below the gateway onto `root`.
+    fn remote_path(&self, uri_path: &str) -> Result<RemotePath, DavError> {
+        let decoded = percent_decode_str(uri_path)
+            .decode_utf8()
+            .map_err(|e| DavError::new(StatusCode::BAD_REQUEST, e))?;
+        let mut segments = Vec::new();
+        for segment in decoded.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    return Err(DavError::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("{} leaves the shared directory", decoded),
+                    ));
+                }
+                segment => segments.push(segment),
+            }
+        }
+        if segments.is_empty() {
+            return Ok(self.root.clone());
+        }
+        self.root
+            .join(segments.join("/"))
+            .map_err(|e| DavError::new(StatusCode::BAD_REQUEST, e))
+    }
+
+    /// The request path of a remote path below `root`.
+    fn href(&self, path: &RemotePath, is_dir: bool) -> String {
+        let root = self.root.as_str().trim_end_matches('/');
+        let relative = path.as_str().strip_prefix(root).unwrap_or_default();
+        let mut href = String::new();
+        for segment in relative.split('/').filter(|segment| !segment.is_empty()) {
+            write!(href, "/{}", utf8_percent_encode(segment, SEGMENT)).unwrap();
+        }
+        if is_dir || href.is_empty() {
+            href.push('/');
+        }
+        href
+    }
+
+    async fn stat(&self, path: &RemotePath) -> Result<FileInfo, DavError> {
+        fs::stat_if_exists(&self.client, path.as_str())
+            .await?
+            .ok_or_else(|| DavError::new(StatusCode::NOT_FOUND, format!("{} not found", path)))
+    }
+
+    /// Fails with `409 Conflict` unless the parent directory of `path` exists.
+    async fn ensure_parent(&self, path: &RemotePath) -> Result<(), DavError> {
+        let Some(parent) = path.parent() else {
+            return Ok(());
+        };
+        match fs::stat_if_exists(&self.client, parent.as_str()).await? {
+            Some(file_info) if file_info.is_dir => Ok(()),
+            _ => Err(DavError::new(
+                StatusCode::CONFLICT,
+                format!("{} does not exist", parent),
+            )),
+        }
+    }
+
+    fn ensure_not_root(&self, path: &RemotePath) -> Result<(), DavError> {
+        if *path == self.root {
+            return Err(DavError::new(
+                StatusCode::FORBIDDEN,
+                "cannot modify the shared directory",
+            ));
+        }
+        Ok(())
+    }
+
+    async fn propfind(&self, parts: &Parts) -> Result<Response, DavError> {
+        let path = self.remote_path(parts.uri.path())?;
+        let file_info = self.stat(&path).await?;
+        let depth = header_str(&parts.headers, "depth").unwrap_or("infinity");
+        let mut entries = vec![(path.clone(), file_info)];
+        if entries[0].1.is_dir && depth != "0" {
+            for file_info in fs::list_dir(&self.client, path.as_str()).await? {
+                let child = path.join(&file_info.name)?;
+                entries.push((child, file_info));
+            }
+        }
+
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+        );
+        for (path, file_info) in &entries {
+            self.write_response(&mut xml, path, file_info);
+        }
+        xml.push_str("</D:multistatus>\n");
+        Ok((
+            StatusCode::MULTI_STATUS,
+            [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+            xml,
+        )
+            .into_response())
+    }
+
+    fn write_response(&self, xml: &mut String, path: &RemotePath, file_info: &FileInfo) {
+        let name = path.file_name().unwrap_or(&file_info.name);
+        xml.push_str("<D:response>");
+        write!(
+            xml,
+            "<D:href>{}</D:href>",
+            self.href(path, file_info.is_dir)
+        )
+        .unwrap();
+        xml.push_str("<D:propstat><D:prop>");
+        write!(xml, "<D:displayname>{}</D:displayname>", escape(name)).unwrap();
+        if file_info.is_dir {
+            xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            xml.push_str("<D:resourcetype/>");
+            write!(
+                xml,
+                "<D:getcontentlength>{}</D:getcontentlength>",
+                file_info.size
+            )
+            .unwrap();
+            xml.push_str("<D:getcontenttype>application/octet-stream</D:getcontenttype>");
+            write!(xml, "<D:getetag>{}</D:getetag>", escape(&etag(file_info))).unwrap();
+        }
+        if let Some(modified) = file_info.modified() {
+            write!(
+                xml,
+                "<D:getlastmodified>{}</D:getlastmodified>",
+                modified.format(HTTP_DATE_FORMAT)
+            )
+            .unwrap();
+        }
+        xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>");
+        xml.push_str("</D:response>\n");
+    }
+
+    async fn get(&self, parts: &Parts) -> Result<Response, DavError> {
+        let path = self.remote_path(parts.uri.path())?;
+        let file_info = self.stat(&path).await?;
+        if file_info.is_dir {
+            return Err(DavError::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("{} is a directory", path),
+            ));
+        }
+        let size = file_info.size as u64;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        headers.insert(header::ETAG, header_value(etag(&file_info))?);
+        if let Some(modified) = file_info.modified() {
+            headers.insert(
+                header::LAST_MODIFIED,
+                header_value(modified.format(HTTP_DATE_FORMAT))?,
+            );
+        }
+
+        if let Some(range) = header_str(&parts.headers, header::RANGE.as_str()) {
+            let Ok(range) = ByteRange::from_http_range(range, size) else {
+                headers.insert(
+                    header::CONTENT_RANGE,
+                    header_value(format!("bytes */{}", size))?,
+                );
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+            };
+            headers.insert(
+                header::CONTENT_RANGE,
+                header_value(format!(
+                    "bytes {}-{}/{}",
+                    range.offset,
+                    range.end() - 1,
+                    size
+                ))?,
+            );
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from(range.length));
+            if parts.method == Method::HEAD {
+                return Ok((StatusCode::PARTIAL_CONTENT, headers).into_response());
+            }
+            let client = self.client.clone();
+            let path = path.to_string();
+            let parts = ByteRange::split(range.length, self.read_size);
+            let stream = stream::iter(parts).then(move |part| {
+                let client = client.clone();
+                let path = path.clone();
+                async move {
+                    fs::read_at(&client, &path, range.offset + part.offset, part.length)
+                        .await
+                        .map_err(io::Error::other)
+                }
+            });
+            let body = Body::from_stream(self.client.throttle(Box::pin(stream)));
+            return Ok((StatusCode::PARTIAL_CONTENT, headers, body).into_response());
+        }
+
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+        if parts.method == Method::HEAD {
+            return Ok((StatusCode::OK, headers).into_response());
+        }
+        let http_fn = ApiStorageDownloadRequest::new()
+            .with_path(path)
+            .stream_builder();
+        let response = self.client.clone().send(http_fn).await?;
+        let stream = response.stream.ok_or_else(|| anyhow!("stream not found"))?;
+        let body = Body::from_stream(self.client.throttle(stream));
+        Ok((StatusCode::OK, headers, body).into_response())
+    }
+
+    async fn put(&self, parts: &Parts, body: Body) -> Result<Response, DavError> {
+        let path = self.remote_path(parts.uri.path())?;
+        self.ensure_not_root(&path)?;
+        let existing = fs::stat_if_exists(&self.client, path.as_str()).await?;
+        if existing.as_ref().is_some_and(|file_info| file_info.is_dir) {
+            return Err(DavError::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("{} is a directory", path),
+            ));
+        }
+        self.ensure_parent(&path).await?;
+        // upload next to the file so that a failed PUT leaves the old version
+        let temp_path = transfer::temp_sibling(path.as_str())?;
+        let reader = StreamReader::new(body.into_data_stream().map_err(io::Error::other));
+        let result = async {
+            transfer::upload_reader(&self.client, reader, &temp_path).await?;
+            transfer::replace_file(&self.client, &temp_path, path.as_str()).await
+        }
+        .await;
+        if result.is_err()
+            && let Err(e) = fs::remove(&self.client, &temp_path).await
+        {
+            warn!("failed to remove temp file {}: {:#}", temp_path, e);
+        }
+        result?;
+        Ok(created_or_replaced(existing.is_some()))
+    }
+
+    async fn delete(&self, parts: &Parts) -> Result<Response, DavError> {
+        let path = self.remote_path(parts.uri.path())?;
+        self.ensure_not_root(&path)?;
+        self.stat(&path).await?;
+        fs::remove(&self.client, path.as_str()).await?;
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+
+    async fn mkcol(&self, parts: &Parts, body: Body) -> Result<Response, DavError> {
+        let path = self.remote_path(parts.uri.path())?;
+        let body = axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(|e| DavError::new(StatusCode::BAD_REQUEST, e))?;
+        if !body.is_empty() {
+            return Err(DavError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "MKCOL with a body is not supported",
+            ));
+        }
+        if fs::stat_if_exists(&self.client, path.as_str())
+            .await?
+            .is_some()
+        {
+            return Err(DavError::new(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("{} already exists", path),
+            ));
+        }
+        self.ensure_parent(&path).await?;
+        fs::mkdir(&self.client, path.as_str()).await?;
+        Ok(StatusCode::CREATED.into_response())
+    }
+
+    async fn r#move(&self, parts: &Parts) -> Result<Response, DavError> {
+        let src = self.remote_path(parts.uri.path())?;
+        let destination = header_str(&parts.headers, "destination")
+            .ok_or_else(|| DavError::new(StatusCode::BAD_REQUEST, "missing Destination header"))?;
+        let destination: Uri = destination
+            .parse()
+            .map_err(|e| DavError::new(StatusCode::BAD_REQUEST, e))?;
+        let dest = self.remote_path(destination.path())?;
+        self.ensure_not_root(&src)?;
+        self.ensure_not_root(&dest)?;
+        if src == dest || dest.as_str().starts_with(&format!("{}/", src)) {
+            return Err(DavError::new(
+                StatusCode::FORBIDDEN,
+                format!("cannot move {} to {}", src, dest),
+            ));
+        }
+        self.stat(&src).await?;
+        let overwrite = header_str(&parts.headers, "overwrite") != Some("F");
+        let replaced = fs::stat_if_exists(&self.client, dest.as_str())
+            .await?
+            .is_some();
+        if replaced {
+            if !overwrite {
+                return Err(DavError::new(
+                    StatusCode::PRECONDITION_FAILED,
+                    format!("{} already exists", dest),
+                ));
+            }
+            // keeps the destination if the move fails
+            transfer::replace_aside(&self.client, src.as_str(), dest.as_str()).await?;
+        } else {
+            self.ensure_parent(&dest).await?;
+            fs::rename(&self.client, src.as_str(), dest.as_str()).await?;
+        }
+        Ok(created_or_replaced(replaced))
+    }
+}
+
+async fn handle(State(gateway): State<Arc<WebDavGateway>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    debug!("{} {}", parts.method, parts.uri);
+    let result = match parts.method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => gateway.propfind(&parts).await,
+        "GET" | "HEAD" => gateway.get(&parts).await,
+        "PUT" => gateway.put(&parts, body).await,
+        "DELETE" => gateway.delete(&parts).await,
+        "MKCOL" => gateway.mkcol(&parts, body).await,
+        "MOVE" => gateway.r#move(&parts).await,
+        method => Err(DavError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            format!("{} is not supported", method),
+        )),
+    };
+    result.unwrap_or_else(IntoResponse::into_response)
+}
+
+fn options() -> Response {
+    (
+        StatusCode::OK,
+        [
+            ("DAV", "1"),
+            ("MS-Author-Via", "DAV"),
+            (header::ALLOW.as_str(), ALLOW),
+        ],
+    )
+        .into_response()
+}
+
+fn created_or_replaced(replaced: bool) -> Response {
+    if replaced {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        StatusCode::CREATED.into_response()
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn header_value(value: impl Display) -> Result<HeaderValue, DavError> {
+    HeaderValue::from_str(&value.to_string())
+        .map_err(|e| DavError::new(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+fn etag(file_info: &FileInfo) -> String {
+    let modified = file_info
+        .modified()
+        .map_or(0, |modified| modified.timestamp_millis());
+    format!("\"{:x}-{:x}\"", file_info.size, modified)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[derive(Debug)]
+struct DavError {
+    status: StatusCode,
+    message: String,
+}
+
+impl DavError {
+    fn new(status: StatusCode, message: impl Display) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A failed storage request.
+impl From<anyhow::Error> for DavError {
+    fn from(error: anyhow::Error) -> Self {
+        warn!("storage request failed: {:#}", error);
+        Self::new(StatusCode::BAD_GATEWAY, format!("{:#}", error))
+    }
+}
+
+impl IntoResponse for DavError {
+    fn into_response(self) -> Response {
+        (self.status, self.message).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::config::OpenApiConfig;
+
+    fn new_gateway(root: &str) -> WebDavGateway {
+        let client = OpenApiClient::new(OpenApiConfig::new());
+        WebDavGateway::new(&client, RemotePath::new(root).unwrap())
+    }
+
+    #[test]
+    fn test_remote_path_and_href() {
+        let gateway = new_gateway("/u1/shared");
+        let path = gateway.remote_path("/data/a%20b.txt").unwrap();
+        assert_eq!(path.as_str(), "/u1/shared/data/a b.txt");
+        assert_eq!(gateway.href(&path, false), "/data/a%20b.txt");
+        assert_eq!(gateway.href(&path.parent().unwrap(), true), "/data/");
+        assert_eq!(gateway.remote_path("/").unwrap().as_str(), "/u1/shared");
+        assert_eq!(gateway.href(&gateway.root, true), "/");
+        assert!(gateway.remote_path("/data/../../u2").is_err());
+
+        let gateway = new_gateway("/");
+        let path = gateway.remote_path("/u1/x").unwrap();
+        assert_eq!(gateway.href(&path, false), "/u1/x");
+    }
+
+    /// Makes emulator requests to `endpoint` fail while their query or body
+    /// contains `needle`, and counts `readAt` calls.
+    #[cfg(feature = "emulator")]
+    #[derive(Default)]
+    struct Faults {
+        read_at_calls: std::sync::atomic::AtomicUsize,
+        fail: std::sync::Mutex<Option<(&'static str, &'static str)>>,
+    }
+
+    #[cfg(feature = "emulator")]
+    #[tokio::test]
+    async fn test_webdav_gateway() -> anyhow::Result<()> {
+        use crate::emulator::testing::TestStorage;
+        use axum::body::to_bytes;
+        use axum::middleware::{self, Next};
+        use std::sync::atomic::Ordering;
+
+        let faults = Arc::new(Faults::default());
+        let storage = TestStorage::start_with({
+            let faults = faults.clone();
+            move |router| {
+                router.layer(middleware::from_fn(move |request: Request, next: Next| {
+                    let faults = faults.clone();
+                    async move {
+                        let endpoint = request.uri().path().to_string();
+                        if endpoint == "/api/storage/readAt" {
+                            faults.read_at_calls.fetch_add(1, Ordering::Relaxed);
+                        }
+                        let fail = *faults.fail.lock().unwrap();
+                        let Some((_, needle)) = fail.filter(|(fail, _)| *fail == endpoint) else {
+                            return next.run(request).await;
+                        };
+                        let (parts, body) = request.into_parts();
+                        let body = to_bytes(body, usize::MAX).await.unwrap();
+                        if parts.uri.query().unwrap_or_default().contains(needle)
+                            || String::from_utf8_lossy(&body).contains(needle)
+                        {
+                            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+                        }
+                        next.run(Request::from_parts(parts, Body::from(body))).await
+                    }
+                }))
+            }
+        })
+        .await?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base = format!("http://{}", listener.local_addr()?);
+        let gateway =
+            WebDavGateway::new(&storage.client, RemotePath::new("/u1")?).with_read_size(4);
+        tokio::spawn(gateway.serve(listener));
+
+        let http = reqwest::Client::new();
+        let dav = |method: &str, path: &str| {
+            http.request(
+                reqwest::Method::from_bytes(method.as_bytes()).unwrap(),
+                format!("{}{}", base, path),
+            )
+        };
+        let get = |path: &str| {
+            let request = dav("GET", path);
+            async move { anyhow::Ok(request.send().await?.text().await?) }
+        };
+        let propfind = || {
+            let request = dav("PROPFIND", "/data").header("Depth", "1");
+            async move { anyhow::Ok(request.send().await?.text().await?) }
+        };
+
+        assert_eq!(dav("MKCOL", "/data").send().await?.status(), 201);
+        assert_eq!(dav("MKCOL", "/data").send().await?.status(), 405);
+        assert_eq!(dav("MKCOL", "/x/y").send().await?.status(), 409);
+        let put = dav("PUT", "/data/a%20b.txt").body("hello world");
+        assert_eq!(put.send().await?.status(), 201);
+        let put = dav("PUT", "/data/a%20b.txt").body("hello there");
+        assert_eq!(put.send().await?.status(), 204);
+
+        let response = dav("GET", "/data/a%20b.txt")
+            .header("Range", "bytes=1-")
+            .send()
+            .await?;
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.headers()["content-range"], "bytes 1-10/11");
+        assert_eq!(response.text().await?, "ello there");
+        assert_eq!(faults.read_at_calls.load(Ordering::Relaxed), 3);
+        assert_eq!(get("/data/a%20b.txt").await?, "hello there");
+
+        let response = dav("PROPFIND", "/data").header("Depth", "1").send().await?;
+        assert_eq!(response.status(), 207);
+        let xml = response.text().await?;
+        assert!(xml.contains("<D:href>/data/</D:href>"));
+        assert!(xml.contains("<D:href>/data/a%20b.txt</D:href>"));
+        assert!(xml.contains("<D:getcontentlength>11</D:getcontentlength>"));
+
+        let response = dav("MOVE", "/data/a%20b.txt")
+            .header("Destination", format!("{}/data/c.txt", base))
+            .send()
+            .await?;
+        assert_eq!(response.status(), 201);
+        assert_eq!(dav("GET", "/data/a%20b.txt").send().await?.status(), 404);
+
+        // a PUT failing after its first part keeps the previous version
+        *faults.fail.lock().unwrap() = Some(("/api/storage/writeAt", ""));
+        let large = vec![b'x'; transfer::PART_SIZE as usize + 1];
+        let put = dav("PUT", "/data/c.txt").body(large);
+        assert_eq!(put.send().await?.status(), 502);
+        *faults.fail.lock().unwrap() = None;
+        assert_eq!(get("/data/c.txt").await?, "hello there");
+        assert!(!propfind().await?.contains(".tmp"));
+
+        // an overwriting MOVE that fails keeps the destination
+        let put = dav("PUT", "/data/new.txt").body("new");
+        assert_eq!(put.send().await?.status(), 201);
+        let overwrite = || {
+            dav("MOVE", "/data/new.txt")
+                .header("Destination", format!("{}/data/c.txt", base))
+                .header("Overwrite", "T")
+        };
+        *faults.fail.lock().unwrap() = Some(("/api/storage/mv", "new.txt"));
+        assert_eq!(overwrite().send().await?.status(), 502);
+        *faults.fail.lock().unwrap() = None;
+        assert_eq!(get("/data/c.txt").await?, "hello there");
+        assert_eq!(overwrite().send().await?.status(), 204);
+        assert_eq!(get("/data/c.txt").await?, "new");
+        let xml = propfind().await?;
+        assert!(!xml.contains(".tmp") && !xml.contains("new.txt"));
+
+        assert_eq!(dav("DELETE", "/data").send().await?.status(), 204);
+        assert_eq!(dav("PROPFIND", "/data").send().await?.status(), 404);
+        Ok(())
+    }
+}